@@ -1,4 +1,4 @@
-use soroban_sdk::{vec, Env, Symbol};
+use soroban_sdk::{vec, Address, Env, Symbol};
 
 pub(crate) fn pledged_amount_changed(e: &Env, total_amount: i128) {
     let topics = (Symbol::new(e, "pledged_amount_changed"),);
@@ -9,4 +9,25 @@ pub(crate) fn target_reached(e: &Env, pledged: i128, target: i128) {
     let topics = (Symbol::new(e, "target_reached"),);
     let event_payload = vec![e, pledged, target];
     e.events().publish(topics, event_payload);
-}
\ No newline at end of file
+}
+
+pub(crate) fn earnings_changed(e: &Env, project_id: u32, annotator: &Address, total: i128) {
+    let topics = (
+        Symbol::new(e, "earnings_changed"),
+        project_id,
+        annotator.clone(),
+    );
+    e.events().publish(topics, total);
+}
+
+pub(crate) fn target_reduced(e: &Env, project_id: u32, old_target: i128, new_target: i128) {
+    let topics = (Symbol::new(e, "target_reduced"), project_id);
+    let event_payload = vec![e, old_target, new_target];
+    e.events().publish(topics, event_payload);
+}
+
+pub(crate) fn deadline_extended(e: &Env, project_id: u32, old_deadline: u64, new_deadline: u64) {
+    let topics = (Symbol::new(e, "deadline_extended"), project_id);
+    let event_payload = vec![e, old_deadline, new_deadline];
+    e.events().publish(topics, event_payload);
+}
@@ -1,9 +1,9 @@
 #![no_std]
-use core::{f32::consts::E, str};
+use core::str;
 
 use soroban_sdk::{
-    contract, contractimpl, contractmeta, contracttype, token, Address, BytesN, ConversionError,
-    Env, IntoVal, Map, Symbol, TryFromVal, Val, Vec,
+    contract, contractimpl, contractmeta, contracttype, token, xdr::ToXdr, Address, Bytes, BytesN,
+    Env, Map, String, Symbol, SymbolStr, TryFromVal, Vec,
 };
 
 mod events;
@@ -19,6 +19,10 @@ pub struct Annotation {
     pub width: u32,
     pub height: u32,
     pub label: Symbol,
+    pub confidence: u32,
+    pub class_id: Option<u32>,
+    pub pending: bool,
+    pub reward: i128,
 }
 
 #[contracttype]
@@ -27,23 +31,108 @@ pub struct DataPoint {
     pub cid: Symbol,
     pub annotated: bool,
     pub annotations: Vec<Annotation>,
+    pub consensus_label: Symbol,
+    pub history: Vec<Annotation>,
+    pub finalized_at: u64,
+}
+
+#[contracttype]
+#[derive(Clone)]
+pub struct Contribution {
+    pub contributor: Address,
+    pub amount: i128,
+    pub timestamp: u64,
+}
+
+#[contracttype]
+#[derive(Clone)]
+pub struct ProjectSummary {
+    pub id: u32,
+    pub name: String,
+    pub target_amount: i128,
+    pub current_amount: i128,
+    pub progress_bps: u32,
+}
+
+#[contracttype]
+#[derive(Clone)]
+pub struct ProjectConfig {
+    pub reward_per_annotation: i128,
+    pub required_annotations: u32,
+    pub batch_rewards: bool,
+    pub completion_quorum_bps: u32,
+    pub num_classes: u32,
+    pub auto_refund_overshoot: bool,
+    pub annotator_stake: i128,
+    pub slash_bps: u32,
+    pub sponsor: Address,
+    pub match_ratio_bps: u32,
+    pub match_cap: i128,
+    pub currency_symbol: Symbol,
+    pub annotation_deadline: u64,
+    pub min_box_area: u32,
+    pub gateway: String,
+    pub label_display: Map<Symbol, Symbol>,
+    pub submit_cooldown: u64,
+    pub soft_cap: i128,
+    pub soft_cap_deadline: u64,
+    pub trusted_signer: BytesN<32>,
+    pub reward_halving_interval: u64,
+    pub label_multiplier: Map<Symbol, u32>,
+    pub track_per_contributor: bool,
+    pub daily_reward_limit: i128,
+    pub early_refunds: bool,
+    pub refund_fee_bps: u32,
+    pub max_total_payout: i128,
+    pub rounding: Symbol,
+    pub max_reward_per_annotator: i128,
+    pub approval_required: bool,
+    pub max_annotations_per_cid: u32,
+    pub instructions: String,
+}
+
+#[contracttype]
+#[derive(Clone)]
+pub struct LegacyState {
+    pub recipient: Address,
+    pub token: Address,
+    pub started: u64,
+    pub deadline: u64,
+    pub target_amount: i128,
+    pub data_point_cids: Vec<Symbol>,
 }
 
 #[contracttype]
 #[derive(Clone)]
 pub struct Project {
     pub id: u32,
-    pub name: Symbol,
-    pub description: Symbol,
+    pub name: String,
+    pub description: String,
     pub recipient: Address,
+    pub token: Address,
     pub started: u64,
     pub deadline: u64,
+    pub annotating_since: Option<u64>,
     pub target_amount: i128,
     pub current_amount: i128,
+    pub matched_amount: i128,
+    pub refund_pool: i128,
+    pub refund_basis: i128,
+    pub contribution_count: u32,
+    pub contributions: Vec<Contribution>,
     pub data_points: Map<Symbol, DataPoint>,
+    pub data_point_count: u32,
     pub contributors_contribution_map: Map<Address, i128>,
+    pub withdrawn: Map<Address, bool>,
     pub annotators_earning_map: Map<Address, i128>,
+    pub pending_rewards: Map<Address, i128>,
+    pub annotator_stakes: Map<Address, i128>,
+    pub disputes: Vec<(Symbol, u32)>,
+    pub last_submit_ledger: Map<Address, u64>,
+    pub daily_earnings: Map<Address, (u64, i128)>,
     pub state: State,
+    pub paused: bool,
+    pub config: ProjectConfig,
 }
 
 #[contracttype]
@@ -53,6 +142,14 @@ pub enum DataKey {
     Project(u32),
     ProjectIDs,
     ProjectCount,
+    Admin,
+    Banned(Address),
+    TotalRaised,
+    TotalAnnotations,
+    FeeCollector,
+    FeeBps,
+    FeesAccrued,
+    Reputation(Address),
 }
 
 #[contracttype]
@@ -65,59 +162,106 @@ pub enum State {
     Expired = 3,
 }
 
+fn get_admin(e: &Env) -> Option<Address> {
+    e.storage().instance().get::<_, Address>(&DataKey::Admin)
+}
+
+// Annotators without an explicit reputation get a single vote.
+fn get_reputation(e: &Env, annotator: &Address) -> u32 {
+    e.storage()
+        .instance()
+        .get::<_, u32>(&DataKey::Reputation(annotator.clone()))
+        .unwrap_or(1)
+}
+
+fn require_project(e: &Env, project_id: u32) {
+    assert!(
+        e.storage().instance().has(&DataKey::Project(project_id)),
+        "project not found"
+    );
+}
+
+fn is_paused(e: &Env, project_id: u32) -> bool {
+    e.storage()
+        .instance()
+        .get::<_, Project>(&DataKey::Project(project_id))
+        .unwrap()
+        .paused
+}
+
 fn get_ledger_timestamp(e: &Env) -> u64 {
     e.ledger().timestamp()
 }
 
 fn get_recipient(e: &Env, project_id: u32) -> Address {
-    return e
-        .storage()
+    e.storage()
+        .instance()
+        .get::<_, Project>(&DataKey::Project(project_id))
+        .unwrap()
+        .recipient
+}
+
+fn get_token(e: &Env, project_id: u32) -> Address {
+    e.storage()
+        .instance()
+        .get::<_, Project>(&DataKey::Project(project_id))
+        .unwrap()
+        .token
+}
+
+fn get_config(e: &Env, project_id: u32) -> ProjectConfig {
+    e.storage()
         .instance()
         .get::<_, Project>(&DataKey::Project(project_id))
         .unwrap()
-        .recipient;
+        .config
 }
 
 fn get_deadline(e: &Env, project_id: u32) -> u64 {
-    return e
-        .storage()
+    e.storage()
         .instance()
         .get::<_, Project>(&DataKey::Project(project_id))
         .unwrap()
-        .deadline;
+        .deadline
 }
 
 fn get_target_amount(e: &Env, project_id: u32) -> i128 {
-    return e
-        .storage()
+    e.storage()
         .instance()
         .get::<_, Project>(&DataKey::Project(project_id))
         .expect("not initialized")
-        .target_amount;
+        .target_amount
+}
+
+fn get_stake(e: &Env, annotator: &Address, project_id: u32) -> i128 {
+    e.storage()
+        .instance()
+        .get::<_, Project>(&DataKey::Project(project_id))
+        .unwrap()
+        .annotator_stakes
+        .get(annotator.clone())
+        .unwrap_or(0)
 }
 
 fn get_user_deposited(e: &Env, adr: &Address, project_id: u32) -> i128 {
-    let user_deposited = e
-        .storage()
+    e.storage()
         .instance()
         .get::<_, Project>(&DataKey::Project(project_id))
         .unwrap()
         .contributors_contribution_map
         .get(adr.clone())
-        .unwrap_or(0);
-    return user_deposited;
+        .unwrap_or(0)
 }
 
 fn get_balance(e: &Env, project_id: u32) -> i128 {
-    return e
-        .storage()
+    e.storage()
         .instance()
         .get::<_, Project>(&DataKey::Project(project_id))
         .unwrap()
-        .current_amount;
+        .current_amount
 }
 
-fn target_reached(e: &Env, token_id: &Address, project_id: u32) -> bool {
+fn target_reached(e: &Env, _token_id: &Address, project_id: u32) -> bool {
     let target_amount = get_target_amount(e, project_id);
     let token_balance = get_balance(e, project_id);
 
@@ -127,87 +271,524 @@ fn target_reached(e: &Env, token_id: &Address, project_id: u32) -> bool {
     false
 }
 
+// Reward for the next annotation, halved for every full interval spent annotating.
+// It never drops below one token.
+fn current_reward(e: &Env, project: &Project) -> i128 {
+    let reward = project.config.reward_per_annotation;
+    let interval = project.config.reward_halving_interval;
+    let since = match project.annotating_since {
+        Some(since) if interval > 0 => since,
+        _ => return reward,
+    };
+    let halvings = (get_ledger_timestamp(e) - since) / interval;
+    if halvings >= 127 {
+        return 1;
+    }
+    let halved = reward >> halvings;
+    if halved > 0 {
+        halved
+    } else {
+        1
+    }
+}
+
+// What the next annotation with `label` pays after multipliers, decay and the payout cap.
+fn annotation_reward(e: &Env, project: &Project, label: &Symbol) -> i128 {
+    // multipliers are percentages, unmapped labels pay the plain reward
+    let multiplier = project
+        .config
+        .label_multiplier
+        .get(label.clone())
+        .unwrap_or(100);
+    let reward = current_reward(e, project) * multiplier as i128 / 100;
+    // the last reward under the payout cap only pays what is left of it
+    let cap = project.config.max_total_payout;
+    if cap > 0 && total_earnings(project) + reward > cap {
+        return cap - total_earnings(project);
+    }
+    reward
+}
+
+// Works out the state a project should be in without touching storage.
+fn compute_state(e: &Env, project: &Project) -> State {
+    let state = match project.state {
+        State::Funding => {
+            // all-or-nothing campaigns fail early when the soft cap is missed
+            let soft_cap_missed = project.config.soft_cap > 0
+                && get_ledger_timestamp(e) > project.config.soft_cap_deadline
+                && project.current_amount < project.config.soft_cap;
+            // a funded project starts annotating even if nobody touched it before the deadline
+            if project.current_amount >= project.target_amount {
+                State::Annotating
+            } else if get_ledger_timestamp(e) > project.deadline || soft_cap_missed {
+                State::Expired
+            } else {
+                State::Funding
+            }
+        }
+        state => state,
+    };
+    // a project funded long ago may already be done annotating as well
+    match state {
+        State::Annotating => {
+            // once the pot can't cover another reward, whatever is left is sweepable.
+            // Batched projects only leave Annotating through `finalize` or a timeout.
+            let timed_out = project.config.annotation_deadline != 0
+                && get_ledger_timestamp(e) > project.config.annotation_deadline;
+            let cap_hit = project.config.max_total_payout > 0
+                && total_earnings(project) >= project.config.max_total_payout;
+            if timed_out
+                || cap_hit
+                || (!project.config.batch_rewards
+                    && project.current_amount < current_reward(e, project))
+            {
+                State::Success
+            } else {
+                State::Annotating
+            }
+        }
+        state => state,
+    }
+}
+
+// The state a project is in right now, for queries that must not persist it.
+fn peek_state(e: &Env, project_id: u32) -> State {
+    compute_state(
+        e,
+        &e.storage()
+            .instance()
+            .get::<_, Project>(&DataKey::Project(project_id))
+            .unwrap(),
+    )
+}
+
 fn get_state(e: &Env, project_id: u32) -> State {
-    let deadline = get_deadline(e, project_id);
-    let token_id = e.current_contract_address();
-    let current_timestamp = get_ledger_timestamp(e);
+    let mut project = e
+        .storage()
+        .instance()
+        .get::<_, Project>(&DataKey::Project(project_id))
+        .unwrap();
+    let state = compute_state(e, &project);
+    if state == State::Success && project.state != State::Success {
+        close_annotation(e, project);
+    } else if state != project.state {
+        project.state = state;
+        e.storage()
+            .instance()
+            .set(&DataKey::Project(project_id), &project);
+    }
+    state
+}
 
-    let current_state = e
+fn is_fully_annotated(project: &Project) -> bool {
+    for data_point in project.data_points.values().iter() {
+        if !data_point.annotated {
+            return false;
+        }
+    }
+    true
+}
+
+// Moves a project to Success and pays out any rewards still held back by batch mode.
+fn close_annotation(e: &Env, mut project: Project) {
+    let pending_rewards = project.pending_rewards.clone();
+    project.pending_rewards = Map::new(e);
+    project.state = State::Success;
+    // refunds are shared out of the pot as it stands now
+    project.refund_pool = project.current_amount;
+    project.refund_basis = refundable_total(&project);
+    e.storage()
+        .instance()
+        .set(&DataKey::Project(project.id), &project);
+
+    for (annotator, reward) in pending_rewards.iter() {
+        transfer(e, &annotator, &reward, project.id);
+    }
+}
+
+// What an annotator may still earn on a project, i128::MAX when uncapped.
+fn annotator_headroom(project: &Project, annotator: &Address) -> i128 {
+    let cap = project.config.max_reward_per_annotator;
+    if cap == 0 {
+        return i128::MAX;
+    }
+    let earned = project
+        .annotators_earning_map
+        .get(annotator.clone())
+        .unwrap_or(0);
+    if earned >= cap {
+        0
+    } else {
+        cap - earned
+    }
+}
+
+// Everything annotators have earned on a project so far.
+fn total_earnings(project: &Project) -> i128 {
+    let mut total: i128 = 0;
+    for earnings in project.annotators_earning_map.values().iter() {
+        total += earnings;
+    }
+    total
+}
+
+// A contributor's cut of the pot left at Success, rounded as configured. Floor
+// rounding leaves the dust behind for the recipient to sweep.
+fn refund_share(e: &Env, project: &Project, deposit: i128) -> i128 {
+    let scaled = deposit * project.refund_pool;
+    let mut share = scaled / project.refund_basis;
+    if project.config.rounding == Symbol::new(e, "ceil") && scaled % project.refund_basis != 0 {
+        share += 1;
+    }
+    if share > project.current_amount {
+        share = project.current_amount;
+    }
+    share
+}
+
+// Pro-rata shares of the Success pot contributors have not claimed yet.
+fn unclaimed_refunds(e: &Env, project: &Project) -> i128 {
+    let mut total: i128 = 0;
+    for deposit in project.contributors_contribution_map.values().iter() {
+        if deposit > 0 {
+            total += refund_share(e, project, deposit);
+        }
+    }
+    if total > project.current_amount {
+        project.current_amount
+    } else {
+        total
+    }
+}
+
+// Deposits contributors have not taken back yet.
+fn refundable_total(project: &Project) -> i128 {
+    let mut total: i128 = 0;
+    for deposit in project.contributors_contribution_map.values().iter() {
+        total += deposit;
+    }
+    total
+}
+
+// Sends whatever is left in a finished project to its recipient, auth is up to the caller.
+fn sweep(e: &Env, project_id: u32) -> i128 {
+    let mut project = e
         .storage()
         .instance()
-        .get::<_, Project>(&DataKey::Project((project_id)))
-        .unwrap()
-        .state;
-    if (current_state == State::Expired) {
-        return current_state;
+        .get::<_, Project>(&DataKey::Project(project_id))
+        .unwrap();
+    let remainder = project.current_amount;
+    project.current_amount = 0;
+    e.storage()
+        .instance()
+        .set(&DataKey::Project(project_id), &project);
+    if remainder > 0 {
+        transfer(e, &project.recipient, &remainder, project_id);
     }
-    if (current_state == State::Funding) {
-        if target_reached(e, &token_id, project_id) {
-            let mut project = e
-                .storage()
-                .instance()
-                .get::<_, Project>(&DataKey::Project((project_id)))
-                .unwrap();
-            project.state = State::Annotating;
-            e.storage()
-                .instance()
-                .set(&DataKey::Project(project_id), &project);
+    events::pledged_amount_changed(e, 0);
+    remainder
+}
+
+// Label with the most votes, each weighted by the annotator's reputation.
+// Ties go to the label that was submitted first.
+fn majority_label(e: &Env, annotations: &Vec<Annotation>) -> Symbol {
+    let mut best = annotations.get(0).unwrap().label;
+    let mut best_count = 0;
+    // annotations awaiting approval don't vote
+    for candidate in annotations.iter().filter(|a| !a.pending) {
+        let mut count = 0;
+        for annotation in annotations.iter().filter(|a| !a.pending) {
+            if annotation.label == candidate.label {
+                count += get_reputation(e, &annotation.annotator);
+            }
+        }
+        if count > best_count {
+            best = candidate.label;
+            best_count = count;
+        }
+    }
+    best
+}
+
+// A data point is finalized once it has collected enough accepted annotations.
+fn refresh_data_point(e: &Env, data_point: &mut DataPoint, required: u32, now: u64) {
+    let accepted = data_point.annotations.iter().filter(|a| !a.pending).count() as u32;
+    let was_annotated = data_point.annotated;
+    data_point.annotated = accepted >= required;
+    if data_point.annotated && !was_annotated {
+        data_point.finalized_at = now;
+    }
+    if data_point.annotated {
+        data_point.consensus_label = majority_label(e, &data_point.annotations);
+    }
+}
+
+// Checks the reward for `label` against the pool and the annotator's limits, then books it.
+// The caller pays it out (unless batched) once the project has been saved.
+fn credit_reward(e: &Env, project: &mut Project, to: &Address, label: &Symbol, now: u64) -> i128 {
+    let reward = annotation_reward(e, project, label);
+    assert!(project.current_amount >= reward, "reward pool exhausted");
+    let limit = project.config.daily_reward_limit;
+    if limit > 0 {
+        // earnings are bucketed per ledger day
+        let day = now / 86_400;
+        let earned_today = match project.daily_earnings.get(to.clone()) {
+            Some((bucket, earned)) if bucket == day => earned,
+            _ => 0,
         };
-        if current_timestamp > deadline {
+        assert!(earned_today + reward <= limit, "daily reward limit");
+        project
+            .daily_earnings
+            .set(to.clone(), (day, earned_today + reward));
+    }
+    assert!(
+        reward <= annotator_headroom(project, to),
+        "annotator cap reached"
+    );
+
+    project.current_amount -= reward;
+    let earnings = project.annotators_earning_map.get(to.clone()).unwrap_or(0) + reward;
+    project.annotators_earning_map.set(to.clone(), earnings);
+    if project.config.batch_rewards {
+        // reserved now, paid out by `finalize`
+        let pending = project.pending_rewards.get(to.clone()).unwrap_or(0);
+        project.pending_rewards.set(to.clone(), pending + reward);
+    }
+    reward
+}
+
+// Bookkeeping shared by accepted annotations, whether on submit or on approval.
+// Runs after `project` has been saved.
+fn annotation_accepted(e: &Env, project: &Project, to: &Address, reward: i128) {
+    let project_id = project.id;
+    let total_annotations: u32 = e
+        .storage()
+        .instance()
+        .get(&DataKey::TotalAnnotations)
+        .unwrap_or(0);
+    e.storage()
+        .instance()
+        .set(&DataKey::TotalAnnotations, &(total_annotations + 1));
+    let earnings = project.annotators_earning_map.get(to.clone()).unwrap_or(0);
+    events::earnings_changed(e, project_id, to, earnings);
+    let batched = project.config.batch_rewards;
+    // check balance and if it can't pay another reward, we change state.
+    get_state(e, project_id);
+    // pay last, once everything above has been recorded
+    if !batched {
+        transfer(e, to, &reward, project_id);
+    }
+}
+
+// Whether `to` may annotate on the project at all.
+fn check_annotator(e: &Env, project_id: u32, to: &Address) {
+    assert!(!is_paused(e, project_id), "project paused");
+    assert!(
+        !e.storage().instance().has(&DataKey::Banned(to.clone())),
+        "annotator banned"
+    );
+}
+
+// Checks an annotation against the project's rules, on submit and on edit alike.
+fn check_annotation(e: &Env, project_id: u32, annotation: &Annotation) {
+    assert!(annotation.confidence <= 100, "confidence out of range");
+    let config = get_config(e, project_id);
+    // an overflowing area is certainly not too small
+    let big_enough = annotation
+        .width
+        .checked_mul(annotation.height)
+        .is_none_or(|area| area >= config.min_box_area);
+    assert!(big_enough, "box too small");
+    let required_stake = config.annotator_stake;
+    if required_stake > 0 {
+        assert!(
+            get_stake(e, &annotation.annotator, project_id) >= required_stake,
+            "stake required"
+        );
+    }
+}
+
+// Shared tail of the submit entry points.
+fn record_annotation(e: &Env, project_id: u32, data_point_cid: Symbol, mut annotation: Annotation) {
+    let to = annotation.annotator.clone();
+    to.require_auth();
+    check_annotator(e, project_id, &to);
+    let state = get_state(e, project_id);
+
+    match state {
+        State::Funding => {
+            panic!("sale is still running")
+        }
+        State::Annotating => {
+            check_annotation(e, project_id, &annotation);
             let mut project = e
                 .storage()
                 .instance()
-                .get::<_, Project>(&DataKey::Project((project_id)))
+                .get::<_, Project>(&DataKey::Project(project_id))
                 .unwrap();
-            project.state = State::Expired;
+            let now = get_ledger_timestamp(e);
+            if let Some(last) = project.last_submit_ledger.get(to.clone()) {
+                assert!(
+                    now >= last.saturating_add(project.config.submit_cooldown),
+                    "cooldown"
+                );
+            }
+            project.last_submit_ledger.set(to.clone(), now);
+            let mut data_point = project.data_points.get(data_point_cid.clone()).unwrap();
+            let cap = project.config.max_annotations_per_cid;
+            assert!(
+                cap == 0 || data_point.annotations.len() < cap,
+                "annotation cap reached"
+            );
+            if project.config.approval_required {
+                // held without reward until the recipient reviews it
+                annotation.pending = true;
+                data_point.annotations.push_back(annotation);
+                project.data_points.set(data_point_cid, data_point);
+                e.storage()
+                    .instance()
+                    .set(&DataKey::Project(project_id), &project);
+                return;
+            }
+
+            let reward = credit_reward(e, &mut project, &to, &annotation.label, now);
+            annotation.reward = reward;
+            data_point.annotations.push_back(annotation);
+            refresh_data_point(e, &mut data_point, project.config.required_annotations, now);
+            project.data_points.set(data_point_cid, data_point);
             e.storage()
                 .instance()
                 .set(&DataKey::Project(project_id), &project);
-        };
-    }
-    if (current_state == State::Annotating) {
-        if get_balance(e, project_id.clone()) < 1 {
+            annotation_accepted(e, &project, &to, reward);
+        }
+        State::Success => {
+            // Contributors take back their pro-rata share of whatever the annotators left.
             let mut project = e
                 .storage()
                 .instance()
-                .get::<_, Project>(&DataKey::Project((project_id)))
+                .get::<_, Project>(&DataKey::Project(project_id))
                 .unwrap();
-            project.state = State::Success;
-            e.storage()
-                .instance()
-                .set(&DataKey::Project(project_id), &project);
-        };
-    }
+            let balance = project
+                .contributors_contribution_map
+                .get(to.clone())
+                .unwrap_or(0);
+            if balance > 0 {
+                let share = refund_share(e, &project, balance);
+                project.current_amount -= share;
+                project.contributors_contribution_map.set(to.clone(), 0);
+                project.withdrawn.set(to.clone(), true);
+                e.storage()
+                    .instance()
+                    .set(&DataKey::Project(project_id), &project);
+                if share > 0 {
+                    transfer(e, &to, &share, project_id);
+                }
+            }
+            let contract_balance = get_balance(e, project_id);
+            events::pledged_amount_changed(e, contract_balance);
+        }
+        State::Expired => {
+            panic!("Withdraw, expired")
+        }
+    };
+}
+
+// Returns a contributor's deposit and marks them as withdrawn.
+fn refund(e: &Env, user: &Address, balance: i128, project_id: u32) {
+    // fail clearly rather than inside the token if the books have drifted
+    assert!(
+        get_balance(e, project_id) >= balance,
+        "insufficient contract balance"
+    );
+    set_user_deposited(e, user, &0, project_id);
+
     let mut project = e
         .storage()
         .instance()
-        .get::<_, Project>(&DataKey::Project((project_id)))
+        .get::<_, Project>(&DataKey::Project(project_id))
         .unwrap();
-    project.state
+    project.current_amount -= balance;
+    project.withdrawn.set(user.clone(), true);
+    e.storage()
+        .instance()
+        .set(&DataKey::Project(project_id), &project);
+
+    transfer(e, user, &balance, project_id);
+}
+
+// Portion of a contribution that is kept, see `auto_refund_overshoot`.
+fn accepted_amount(project: &Project, amount: i128) -> i128 {
+    let remaining = project.target_amount - project.current_amount;
+    if project.config.auto_refund_overshoot && amount > remaining {
+        return remaining;
+    }
+    amount
+}
+
+// The global fee taken from a contribution of `amount`.
+fn contribution_fee(e: &Env, amount: i128) -> i128 {
+    let fee_bps: u32 = e.storage().instance().get(&DataKey::FeeBps).unwrap_or(0);
+    amount * fee_bps as i128 / 10_000
+}
+
+// Books the global fee on a contribution and returns it.
+fn accrue_fee(e: &Env, token: &Address, amount: i128) -> i128 {
+    let fee = contribution_fee(e, amount);
+    credit_fees(e, token, fee);
+    fee
+}
+
+fn credit_fees(e: &Env, token: &Address, fee: i128) {
+    if fee > 0 {
+        let mut fees: Map<Address, i128> = e
+            .storage()
+            .instance()
+            .get(&DataKey::FeesAccrued)
+            .unwrap_or(Map::new(e));
+        let accrued = fees.get(token.clone()).unwrap_or(0);
+        fees.set(token.clone(), accrued + fee);
+        e.storage().instance().set(&DataKey::FeesAccrued, &fees);
+    }
+}
+
+// Sponsor match owed for an accepted contribution, after the cap and overshoot rules.
+fn matched_amount(project: &Project, accepted: i128) -> i128 {
+    if project.config.match_ratio_bps == 0 {
+        return 0;
+    }
+    let mut matched = accepted * project.config.match_ratio_bps as i128 / 10_000;
+    let cap_left = project.config.match_cap - project.matched_amount;
+    if matched > cap_left {
+        matched = cap_left;
+    }
+    if project.config.auto_refund_overshoot {
+        let remaining = project.target_amount - project.current_amount - accepted;
+        if matched > remaining {
+            matched = remaining;
+        }
+    }
+    matched
 }
 
 fn set_user_deposited(e: &Env, user: &Address, amount: &i128, project_id: u32) {
     let mut project = e
         .storage()
         .instance()
-        .get::<_, Project>(&DataKey::Project((project_id)))
+        .get::<_, Project>(&DataKey::Project(project_id))
         .unwrap();
-    let current_contributions = project
-        .contributors_contribution_map
-        .get(user.clone())
-        .unwrap_or(0);
     project
         .contributors_contribution_map
-        .set(user.clone(), current_contributions + amount);
+        .set(user.clone(), *amount);
     e.storage()
         .instance()
         .set(&DataKey::Project(project_id), &project);
 }
 
 // Transfer tokens from the contract to the recipient
-fn transfer(e: &Env, to: &Address, amount: &i128) {
-    let token_contract_id = e.current_contract_address();
+fn transfer(e: &Env, to: &Address, amount: &i128, project_id: u32) {
+    let token_contract_id = get_token(e, project_id);
     let client = token::Client::new(e, &token_contract_id);
+    assert!(to != &e.current_contract_address(), "invalid recipient");
     client.transfer(&e.current_contract_address(), to, amount);
 }
 
@@ -217,6 +798,49 @@ contractmeta!(
     val = "DataAnnotate Contract that help CrowdFund and Data Annotate"
 );
 
+// Configuration with every optional feature switched off
+pub(crate) fn default_config(e: &Env) -> ProjectConfig {
+    ProjectConfig {
+        reward_per_annotation: 1,
+        required_annotations: 1,
+        batch_rewards: false,
+        completion_quorum_bps: 0,
+        num_classes: 0,
+        auto_refund_overshoot: false,
+        annotator_stake: 0,
+        slash_bps: 0,
+        // the all-zero account, matching stays off while match_ratio_bps is 0
+        sponsor: Address::from_string(&String::from_str(
+            e,
+            "GAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAWHF",
+        )),
+        match_ratio_bps: 0,
+        match_cap: 0,
+        currency_symbol: Symbol::new(e, ""),
+        annotation_deadline: 0,
+        min_box_area: 0,
+        gateway: String::from_str(e, ""),
+        label_display: Map::new(e),
+        submit_cooldown: 0,
+        soft_cap: 0,
+        soft_cap_deadline: 0,
+        // all zeroes is not a usable key, signed submissions stay off
+        trusted_signer: BytesN::from_array(e, &[0; 32]),
+        reward_halving_interval: 0,
+        label_multiplier: Map::new(e),
+        track_per_contributor: true,
+        daily_reward_limit: 0,
+        early_refunds: false,
+        refund_fee_bps: 0,
+        max_total_payout: 0,
+        rounding: Symbol::new(e, "floor"),
+        max_reward_per_annotator: 0,
+        approval_required: false,
+        max_annotations_per_cid: 0,
+        instructions: String::from_str(e, ""),
+    }
+}
+
 fn get_project_ids(e: Env) -> Vec<u32> {
     e.storage()
         .instance()
@@ -224,241 +848,1971 @@ fn get_project_ids(e: Env) -> Vec<u32> {
         .unwrap()
 }
 
+// Shortest data point CID accepted.
+const MIN_CID_LEN: usize = 4;
+
+// Cheap sanity check on a CID. A Symbol already holds at most 32 characters out
+// of [a-zA-Z0-9_], so only the lower length bound is left to check.
+fn valid_cid(e: &Env, cid: &Symbol) -> bool {
+    let cid = SymbolStr::try_from_val(e, &cid.to_symbol_val()).unwrap();
+    cid.len() >= MIN_CID_LEN
+}
+
+#[allow(clippy::too_many_arguments)]
+fn create_project(
+    e: &Env,
+    recipient: Address,
+    deadline: u64,
+    target_amount: i128,
+    data_point_cids: Vec<Symbol>,
+    name: String,
+    description: String,
+    token: Address,
+    config: ProjectConfig,
+) -> u32 {
+    let mut project_count: u32 = e
+        .storage()
+        .instance()
+        .get::<_, u32>(&DataKey::ProjectCount)
+        .unwrap_or(0);
+    let id = project_count;
+    project_count += 1;
+    let mut data_points: Map<Symbol, DataPoint> = Map::new(e);
+    for cid in data_point_cids.iter() {
+        assert!(valid_cid(e, &cid), "invalid cid");
+        data_points.set(
+            cid.clone(),
+            DataPoint {
+                cid: cid.clone(),
+                annotated: false,
+                annotations: Vec::new(e),
+                consensus_label: Symbol::new(e, ""),
+                history: Vec::new(e),
+                finalized_at: 0,
+            },
+        );
+    }
+    let contributors_contribution_map: Map<Address, i128> = Map::new(e);
+    let annotators_earnings_map: Map<Address, i128> = Map::new(e);
+    // paying the contract itself would loop refunds and sweeps back into the pot
+    assert!(
+        recipient != e.current_contract_address(),
+        "invalid recipient"
+    );
+    assert!(config.reward_per_annotation > 0, "reward must be positive");
+    assert!(
+        config.required_annotations > 0,
+        "required annotations must be positive"
+    );
+    // the funded pot has to be able to pay for every declared data point
+    assert!(
+        data_point_cids.len() as i128 * config.reward_per_annotation <= target_amount,
+        "target below work cost"
+    );
+    assert!(config.annotator_stake >= 0, "stake must not be negative");
+    assert!(config.slash_bps <= 10_000, "slash_bps above 100%");
+    assert!(config.refund_fee_bps <= 10_000, "refund fee above 100%");
+    assert!(config.completion_quorum_bps <= 10_000, "quorum above 100%");
+    assert!(config.match_cap >= 0, "match cap must not be negative");
+    assert!(
+        config.rounding == Symbol::new(e, "floor") || config.rounding == Symbol::new(e, "ceil"),
+        "unknown rounding mode"
+    );
+    assert!(
+        config.max_total_payout >= 0,
+        "payout cap must not be negative"
+    );
+    assert!(
+        config.max_reward_per_annotator >= 0,
+        "annotator cap must not be negative"
+    );
+    for multiplier in config.label_multiplier.values().iter() {
+        assert!(multiplier > 0, "multiplier must be positive");
+    }
+    assert!(
+        config.annotation_deadline == 0 || config.annotation_deadline > deadline,
+        "annotation deadline before funding deadline"
+    );
+    assert!(config.soft_cap <= target_amount, "soft cap above target");
+    assert!(
+        config.soft_cap == 0 || config.soft_cap_deadline <= deadline,
+        "soft cap deadline after funding deadline"
+    );
+
+    let project = Project {
+        id,
+        name,
+        description,
+        recipient,
+        token,
+        state: State::Funding,
+        started: get_ledger_timestamp(e),
+        contributors_contribution_map,
+        withdrawn: Map::new(e),
+        annotators_earning_map: annotators_earnings_map,
+        pending_rewards: Map::new(e),
+        annotator_stakes: Map::new(e),
+        disputes: Vec::new(e),
+        last_submit_ledger: Map::new(e),
+        daily_earnings: Map::new(e),
+        deadline,
+        annotating_since: None,
+        target_amount,
+        current_amount: 0,
+        matched_amount: 0,
+        refund_pool: 0,
+        refund_basis: 0,
+        contribution_count: 0,
+        contributions: Vec::new(e),
+        data_point_count: data_points.len(),
+        data_points,
+        paused: false,
+        config,
+    };
+    e.storage().instance().set(&DataKey::Project(id), &project);
+    e.storage()
+        .instance()
+        .set(&DataKey::ProjectCount, &project_count);
+
+    let mut project_ids: Vec<u32> = e
+        .storage()
+        .instance()
+        .get::<_, Vec<u32>>(&DataKey::ProjectIDs)
+        .unwrap_or(Vec::new(e));
+    project_ids.push_back(id);
+    e.storage()
+        .instance()
+        .set(&DataKey::ProjectIDs, &project_ids);
+    id
+}
+
 #[contract]
 struct DataAnnotate;
 
 #[contractimpl]
 #[allow(clippy::needless_pass_by_value)]
 impl DataAnnotate {
+    #[allow(clippy::too_many_arguments)]
     pub fn initialize(
         e: Env,
         recipient: Address,
         deadline: u64,
         target_amount: i128,
         data_point_cids: Vec<Symbol>,
-        name: Symbol,
-        description: Symbol,
+        name: String,
+        description: String,
+        token: Address,
+        config: ProjectConfig,
     ) {
-        let mut project_count: u32 = e
-            .storage()
-            .instance()
-            .get::<_, u32>(&DataKey::ProjectCount)
-            .unwrap_or(0);
-        let id = project_count;
-        project_count += 1;
-        let mut data_points: Map<Symbol, DataPoint> = Map::new(&e);
-        for cid in data_point_cids.iter() {
-            data_points.set(
-                cid.clone(),
-                DataPoint {
-                    cid: cid.clone(),
-                    annotated: false,
-                    annotations: Vec::new(&e),
-                },
+        create_project(
+            &e,
+            recipient,
+            deadline,
+            target_amount,
+            data_point_cids,
+            name,
+            description,
+            token,
+            config,
+        );
+    }
+
+    pub fn migrate_from_legacy(e: Env, legacy: LegacyState) -> u32 {
+        get_admin(&e).expect("admin not set").require_auth();
+        let id = create_project(
+            &e,
+            legacy.recipient,
+            legacy.deadline,
+            legacy.target_amount,
+            legacy.data_point_cids,
+            String::from_str(&e, ""),
+            String::from_str(&e, ""),
+            legacy.token,
+            default_config(&e),
+        );
+
+        // keep the campaign age the legacy contract reported
+        let mut project = e
+            .storage()
+            .instance()
+            .get::<_, Project>(&DataKey::Project(id))
+            .unwrap();
+        project.started = legacy.started;
+        e.storage().instance().set(&DataKey::Project(id), &project);
+        id
+    }
+
+    pub fn set_admin(e: Env, admin: Address) {
+        // The first admin claims the role, afterwards only the current admin can hand it over.
+        match get_admin(&e) {
+            Some(current) => current.require_auth(),
+            None => admin.require_auth(),
+        }
+        e.storage().instance().set(&DataKey::Admin, &admin);
+    }
+
+    pub fn set_fee_collector(e: Env, collector: Address, fee_bps: u32) {
+        get_admin(&e).expect("admin not set").require_auth();
+        assert!(fee_bps <= 10_000, "fee above 100%");
+        e.storage()
+            .instance()
+            .set(&DataKey::FeeCollector, &collector);
+        e.storage().instance().set(&DataKey::FeeBps, &fee_bps);
+    }
+
+    // Fees are accrued per token and paid out in one go instead of on every contribution.
+    pub fn collect_fees(e: Env) {
+        let collector = e
+            .storage()
+            .instance()
+            .get::<_, Address>(&DataKey::FeeCollector)
+            .expect("fee collector not set");
+        collector.require_auth();
+        let fees: Map<Address, i128> = e
+            .storage()
+            .instance()
+            .get(&DataKey::FeesAccrued)
+            .unwrap_or(Map::new(&e));
+        e.storage().instance().remove(&DataKey::FeesAccrued);
+        for (token, amount) in fees.iter() {
+            token::Client::new(&e, &token).transfer(
+                &e.current_contract_address(),
+                &collector,
+                &amount,
+            );
+        }
+    }
+
+    pub fn fees_accrued(e: Env, token: Address) -> i128 {
+        e.storage()
+            .instance()
+            .get::<_, Map<Address, i128>>(&DataKey::FeesAccrued)
+            .unwrap_or(Map::new(&e))
+            .get(token)
+            .unwrap_or(0)
+    }
+
+    // Sends out tokens sent here by mistake. Funding tokens of any project are off
+    // limits so project funds can't leave this way.
+    pub fn recover_token(e: Env, token: Address, to: Address, amount: i128) {
+        get_admin(&e).expect("admin not set").require_auth();
+        for project_id in Self::project_ids(e.clone()).iter() {
+            assert!(token != get_token(&e, project_id), "project token");
+        }
+        token::Client::new(&e, &token).transfer(&e.current_contract_address(), &to, &amount);
+    }
+
+    pub fn ban_annotator(e: Env, annotator: Address) {
+        get_admin(&e).expect("admin not set").require_auth();
+        e.storage()
+            .instance()
+            .set(&DataKey::Banned(annotator), &true);
+    }
+
+    pub fn unban_annotator(e: Env, annotator: Address) {
+        get_admin(&e).expect("admin not set").require_auth();
+        e.storage().instance().remove(&DataKey::Banned(annotator));
+    }
+
+    // Sets how much weight the annotator's votes carry in consensus.
+    pub fn set_reputation(e: Env, annotator: Address, value: u32) {
+        get_admin(&e).expect("admin not set").require_auth();
+        e.storage()
+            .instance()
+            .set(&DataKey::Reputation(annotator), &value);
+    }
+
+    pub fn reputation(e: Env, annotator: Address) -> u32 {
+        get_reputation(&e, &annotator)
+    }
+
+    pub fn reopen(e: Env, project_id: u32, new_deadline: u64) {
+        get_admin(&e).expect("admin not set").require_auth();
+        assert!(
+            get_state(&e, project_id) == State::Expired,
+            "project not expired"
+        );
+        assert!(
+            new_deadline > get_ledger_timestamp(&e),
+            "deadline must be in the future"
+        );
+        let mut project = e
+            .storage()
+            .instance()
+            .get::<_, Project>(&DataKey::Project(project_id))
+            .unwrap();
+        // once someone took their deposit back the pot no longer matches the pledges
+        assert!(project.withdrawn.is_empty(), "withdrawals already made");
+        assert!(
+            project.config.annotation_deadline == 0
+                || project.config.annotation_deadline > new_deadline,
+            "annotation deadline before funding deadline"
+        );
+
+        project.deadline = new_deadline;
+        if project.config.soft_cap > 0 {
+            // a missed soft cap would expire the project again straight away
+            project.config.soft_cap_deadline = new_deadline;
+        }
+        project.state = State::Funding;
+        e.storage()
+            .instance()
+            .set(&DataKey::Project(project_id), &project);
+    }
+
+    pub fn delete_project(e: Env, project_id: u32) {
+        get_admin(&e).expect("admin not set").require_auth();
+        let state = get_state(&e, project_id);
+        assert!(
+            state == State::Expired || state == State::Success,
+            "project still active"
+        );
+        let project = e
+            .storage()
+            .instance()
+            .get::<_, Project>(&DataKey::Project(project_id))
+            .unwrap();
+        assert!(refundable_total(&project) == 0, "deposits remain");
+        // nothing the project still holds may be orphaned by the deletion
+        assert!(project.current_amount == 0, "funds remain");
+        for staked in project.annotator_stakes.values().iter() {
+            assert!(staked == 0, "stakes remain");
+        }
+        for pending in project.pending_rewards.values().iter() {
+            assert!(pending == 0, "rewards pending");
+        }
+
+        e.storage().instance().remove(&DataKey::Project(project_id));
+        let mut project_ids = get_project_ids(e.clone());
+        if let Some(index) = project_ids.first_index_of(project_id) {
+            project_ids.remove(index);
+        }
+        e.storage()
+            .instance()
+            .set(&DataKey::ProjectIDs, &project_ids);
+    }
+
+    pub fn set_project_paused(e: Env, caller: Address, project_id: u32, paused: bool) {
+        caller.require_auth();
+        let mut project = e
+            .storage()
+            .instance()
+            .get::<_, Project>(&DataKey::Project(project_id))
+            .unwrap();
+        assert!(
+            caller == project.recipient || Some(caller) == get_admin(&e),
+            "not authorized"
+        );
+        project.paused = paused;
+        e.storage()
+            .instance()
+            .set(&DataKey::Project(project_id), &project);
+    }
+
+    pub fn is_project_paused(e: Env, project_id: u32) -> bool {
+        is_paused(&e, project_id)
+    }
+
+    pub fn global_stats(e: Env) -> (u32, i128, u32) {
+        let storage = e.storage().instance();
+        (
+            storage.get(&DataKey::ProjectCount).unwrap_or(0),
+            storage.get(&DataKey::TotalRaised).unwrap_or(0),
+            storage.get(&DataKey::TotalAnnotations).unwrap_or(0),
+        )
+    }
+
+    // Live token holdings of the whole contract, summed over every project token.
+    // Compare against the projects' `current_amount` to spot drift.
+    pub fn contract_balance(e: Env) -> i128 {
+        let mut tokens: Vec<Address> = Vec::new(&e);
+        for project_id in Self::project_ids(e.clone()).iter() {
+            let token = get_token(&e, project_id);
+            if !tokens.contains(&token) {
+                tokens.push_back(token);
+            }
+        }
+        let mut total: i128 = 0;
+        for token in tokens.iter() {
+            total += token::Client::new(&e, &token).balance(&e.current_contract_address());
+        }
+        total
+    }
+
+    // Projects still funding, most funded (relative to target) first.
+    pub fn projects_by_progress(e: Env, limit: u32) -> Vec<ProjectSummary> {
+        let mut sorted: Vec<ProjectSummary> = Vec::new(&e);
+        for project_id in Self::project_ids(e.clone()).iter() {
+            if peek_state(&e, project_id) != State::Funding {
+                continue;
+            }
+            let project = e
+                .storage()
+                .instance()
+                .get::<_, Project>(&DataKey::Project(project_id))
+                .unwrap();
+            let progress_bps = if project.target_amount > 0 {
+                (project.current_amount * 10_000 / project.target_amount) as u32
+            } else {
+                10_000
+            };
+            let summary = ProjectSummary {
+                id: project.id,
+                name: project.name,
+                target_amount: project.target_amount,
+                current_amount: project.current_amount,
+                progress_bps,
+            };
+            // insertion sort, ties keep creation order
+            let mut at = sorted.len();
+            for (i, other) in sorted.iter().enumerate() {
+                if summary.progress_bps > other.progress_bps {
+                    at = i as u32;
+                    break;
+                }
+            }
+            sorted.insert(at, summary);
+        }
+        while sorted.len() > limit {
+            sorted.pop_back();
+        }
+        sorted
+    }
+
+    pub fn project_ids(e: Env) -> Vec<u32> {
+        e.storage()
+            .instance()
+            .get::<_, Vec<u32>>(&DataKey::ProjectIDs)
+            .unwrap_or(Vec::new(&e))
+    }
+
+    pub fn get_projects(e: Env) -> Vec<Project> {
+        let mut projects: Vec<Project> = Vec::new(&e);
+        let project_ids = get_project_ids(e.clone());
+        for project_id in project_ids.iter() {
+            // an id can outlive its project entry, skip those rather than fail the listing
+            if let Some(project) = e
+                .storage()
+                .instance()
+                .get::<_, Project>(&DataKey::Project(project_id))
+            {
+                projects.push_back(project);
+            }
+        }
+        projects
+    }
+
+    pub fn get_projects_by_ids(e: Env, ids: Vec<u32>) -> Vec<Project> {
+        let mut projects: Vec<Project> = Vec::new(&e);
+        for project_id in ids.iter() {
+            if let Some(project) = e
+                .storage()
+                .instance()
+                .get::<_, Project>(&DataKey::Project(project_id))
+            {
+                projects.push_back(project);
+            }
+        }
+        projects
+    }
+
+    pub fn get_project(e: Env, project_id: u32, include_annotations: bool) -> Project {
+        let mut project = e
+            .storage()
+            .instance()
+            .get::<_, Project>(&DataKey::Project(project_id))
+            .unwrap();
+        if !include_annotations {
+            // keep the annotated flags, drop the payload
+            for (cid, mut data_point) in project.data_points.clone().iter() {
+                data_point.annotations = Vec::new(&e);
+                data_point.history = Vec::new(&e);
+                project.data_points.set(cid, data_point);
+            }
+        }
+        project
+    }
+
+    pub fn is_recipient(e: Env, project_id: u32, who: Address) -> bool {
+        get_recipient(&e, project_id) == who
+    }
+
+    // Full copy of the stored project for off-chain backup. Projects too large to
+    // return in one call can be backed up through `snapshot_data_points` instead.
+    pub fn snapshot(e: Env, project_id: u32) -> Project {
+        e.storage()
+            .instance()
+            .get::<_, Project>(&DataKey::Project(project_id))
+            .unwrap()
+    }
+
+    // XDR of `(id, recipient, token, deadline, target_amount, current_amount, state)`
+    // so other contracts can read the key fields without the whole project.
+    pub fn project_bytes(e: Env, project_id: u32) -> Bytes {
+        let state = peek_state(&e, project_id);
+        let project = e
+            .storage()
+            .instance()
+            .get::<_, Project>(&DataKey::Project(project_id))
+            .unwrap();
+        (
+            project.id,
+            project.recipient,
+            project.token,
+            project.deadline,
+            project.target_amount,
+            project.current_amount,
+            state as u32,
+        )
+            .to_xdr(&e)
+    }
+
+    pub fn snapshot_data_points(e: Env, project_id: u32, start: u32, limit: u32) -> Vec<DataPoint> {
+        let project = e
+            .storage()
+            .instance()
+            .get::<_, Project>(&DataKey::Project(project_id))
+            .unwrap();
+        let mut page: Vec<DataPoint> = Vec::new(&e);
+        for data_point in project.data_points.values().iter().skip(start as usize) {
+            if page.len() >= limit {
+                break;
+            }
+            page.push_back(data_point);
+        }
+        page
+    }
+
+    pub fn projects_of(e: Env, recipient: Address) -> Vec<u32> {
+        let mut ids: Vec<u32> = Vec::new(&e);
+        for project in Self::get_projects(e.clone()).iter() {
+            if project.recipient == recipient {
+                ids.push_back(project.id);
+            }
+        }
+        ids
+    }
+
+    pub fn contributed_projects(e: Env, user: Address) -> Vec<u32> {
+        let mut ids: Vec<u32> = Vec::new(&e);
+        for project in Self::get_projects(e.clone()).iter() {
+            if project
+                .contributors_contribution_map
+                .contains_key(user.clone())
+            {
+                ids.push_back(project.id);
+            }
+        }
+        ids
+    }
+
+    pub fn started(e: Env, project_id: u32) -> u64 {
+        e.storage()
+            .instance()
+            .get::<_, Project>(&DataKey::Project(project_id))
+            .unwrap()
+            .started
+    }
+
+    pub fn deadline(e: Env, project_id: u32) -> u64 {
+        get_deadline(&e, project_id)
+    }
+
+    pub fn time_remaining(e: Env, project_id: u32) -> u64 {
+        get_deadline(&e, project_id).saturating_sub(get_ledger_timestamp(&e))
+    }
+
+    // (days, hours, minutes, seconds) until the deadline, all zero once it has passed.
+    pub fn countdown(e: Env, project_id: u32) -> (u64, u64, u64, u64) {
+        let remaining = Self::time_remaining(e, project_id);
+        (
+            remaining / 86_400,
+            remaining % 86_400 / 3_600,
+            remaining % 3_600 / 60,
+            remaining % 60,
+        )
+    }
+
+    pub fn state(e: Env, project_id: u32) -> u32 {
+        peek_state(&e, project_id) as u32
+    }
+
+    pub fn next_action(e: Env, project_id: u32, user: Address) -> Symbol {
+        if is_paused(&e, project_id) {
+            return Symbol::new(&e, "none");
+        }
+        match peek_state(&e, project_id) {
+            State::Funding => Symbol::new(&e, "contribute"),
+            State::Annotating => {
+                if e.storage().instance().has(&DataKey::Banned(user)) {
+                    Symbol::new(&e, "none")
+                } else {
+                    Symbol::new(&e, "annotate")
+                }
+            }
+            State::Expired => {
+                if get_user_deposited(&e, &user, project_id) > 0 {
+                    Symbol::new(&e, "withdraw")
+                } else {
+                    Symbol::new(&e, "none")
+                }
+            }
+            // contributors claim their share of what is left by calling submit
+            State::Success => {
+                if get_user_deposited(&e, &user, project_id) > 0 {
+                    Symbol::new(&e, "refund")
+                } else {
+                    Symbol::new(&e, "none")
+                }
+            }
+        }
+    }
+
+    pub fn is_annotating(e: Env, project_id: u32) -> bool {
+        peek_state(&e, project_id) == State::Annotating
+    }
+
+    // Raw persisted state, which can lag behind what `state` reports until a
+    // mutating call runs the transitions.
+    pub fn stored_state(e: Env, project_id: u32) -> u32 {
+        e.storage()
+            .instance()
+            .get::<_, Project>(&DataKey::Project(project_id))
+            .unwrap()
+            .state as u32
+    }
+
+    pub fn target(e: Env, project_id: u32) -> i128 {
+        get_target_amount(&e, project_id)
+    }
+
+    pub fn is_target_reached(e: Env, project_id: u32) -> bool {
+        target_reached(&e, &get_token(&e, project_id), project_id)
+    }
+
+    pub fn remaining_to_target(e: Env, project_id: u32) -> i128 {
+        let remaining = get_target_amount(&e, project_id) - get_balance(&e, project_id);
+        if remaining > 0 {
+            remaining
+        } else {
+            0
+        }
+    }
+
+    pub fn token(e: Env, project_id: u32) -> Address {
+        get_token(&e, project_id)
+    }
+
+    pub fn currency(e: Env, project_id: u32) -> Symbol {
+        get_config(&e, project_id).currency_symbol
+    }
+
+    // Gateway URLs don't fit in a Symbol, so the hint is kept as a String.
+    pub fn gateway(e: Env, project_id: u32) -> String {
+        get_config(&e, project_id).gateway
+    }
+
+    // Guidance for annotators, empty when the recipient gave none.
+    pub fn instructions(e: Env, project_id: u32) -> String {
+        get_config(&e, project_id).instructions
+    }
+
+    // Unmapped codes are shown as they are.
+    pub fn label_display(e: Env, project_id: u32, code: Symbol) -> Symbol {
+        get_config(&e, project_id)
+            .label_display
+            .get(code.clone())
+            .unwrap_or(code)
+    }
+
+    pub fn current_reward(e: Env, project_id: u32) -> i128 {
+        current_reward(
+            &e,
+            &e.storage()
+                .instance()
+                .get::<_, Project>(&DataKey::Project(project_id))
+                .unwrap(),
+        )
+    }
+
+    // Payout `annotator` would get for labelling `data_point_cid` with `label` right now.
+    // Submits that would be rejected or paid later, e.g. by a cap, the daily limit, a
+    // cooldown or a pending approval, quote 0. Box checks are left to `submit`.
+    pub fn quote_reward(
+        e: Env,
+        project_id: u32,
+        data_point_cid: Symbol,
+        label: Symbol,
+        annotator: Address,
+    ) -> i128 {
+        if peek_state(&e, project_id) != State::Annotating {
+            return 0;
+        }
+        let project = e
+            .storage()
+            .instance()
+            .get::<_, Project>(&DataKey::Project(project_id))
+            .unwrap();
+        let data_point = project
+            .data_points
+            .get(data_point_cid)
+            .expect("unknown data point");
+        let config = &project.config;
+        let now = get_ledger_timestamp(&e);
+        let cooling_down = project
+            .last_submit_ledger
+            .get(annotator.clone())
+            .is_some_and(|last| now < last.saturating_add(config.submit_cooldown));
+        if project.paused
+            || e.storage()
+                .instance()
+                .has(&DataKey::Banned(annotator.clone()))
+            || get_stake(&e, &annotator, project_id) < config.annotator_stake
+            || cooling_down
+            || config.approval_required
+            || (config.max_annotations_per_cid > 0
+                && data_point.annotations.len() >= config.max_annotations_per_cid)
+        {
+            return 0;
+        }
+        let reward = annotation_reward(&e, &project, &label);
+        if reward > project.current_amount || reward > annotator_headroom(&project, &annotator) {
+            return 0;
+        }
+        let limit = project.config.daily_reward_limit;
+        if limit > 0 {
+            let day = now / 86_400;
+            let earned_today = match project.daily_earnings.get(annotator) {
+                Some((bucket, earned)) if bucket == day => earned,
+                _ => 0,
+            };
+            if earned_today + reward > limit {
+                return 0;
+            }
+        }
+        reward
+    }
+
+    pub fn reward_budget_status(e: Env, project_id: u32) -> (i128, i128) {
+        let state = peek_state(&e, project_id);
+        let mut project = e
+            .storage()
+            .instance()
+            .get::<_, Project>(&DataKey::Project(project_id))
+            .unwrap();
+
+        let paid_out = total_earnings(&project);
+
+        // deposits are owed back in full while funding or after expiry and pro-rata
+        // at Success, while annotating they are committed to rewards
+        let mut refundable: i128 = 0;
+        if state == State::Funding || state == State::Expired {
+            refundable = refundable_total(&project);
+        } else if state == State::Success {
+            if project.state != State::Success {
+                // not closed yet, the shares would come out of the pot as it stands
+                project.refund_pool = project.current_amount;
+                project.refund_basis = refundable_total(&project);
+            }
+            refundable = unclaimed_refunds(&e, &project);
+        }
+        let remaining = project.current_amount - refundable;
+        (paid_out, if remaining > 0 { remaining } else { 0 })
+    }
+
+    pub fn get_annotators(e: Env, project_id: u32) -> Vec<Address> {
+        e.storage()
+            .instance()
+            .get::<_, Project>(&DataKey::Project(project_id))
+            .unwrap()
+            .annotators_earning_map
+            .keys()
+    }
+
+    // (contributed, earned, annotations made) for someone who both funds and annotates.
+    pub fn user_summary(e: Env, project_id: u32, user: Address) -> (i128, i128, u32) {
+        let project = e
+            .storage()
+            .instance()
+            .get::<_, Project>(&DataKey::Project(project_id))
+            .unwrap();
+        let mut annotations_made = 0;
+        for data_point in project.data_points.values().iter() {
+            for annotation in data_point.annotations.iter() {
+                if annotation.annotator == user {
+                    annotations_made += 1;
+                }
+            }
+        }
+        (
+            get_user_deposited(&e, &user, project_id),
+            project.annotators_earning_map.get(user).unwrap_or(0),
+            annotations_made,
+        )
+    }
+
+    pub fn annotator_headroom(e: Env, project_id: u32, annotator: Address) -> i128 {
+        annotator_headroom(
+            &e.storage()
+                .instance()
+                .get::<_, Project>(&DataKey::Project(project_id))
+                .unwrap(),
+            &annotator,
+        )
+    }
+
+    // What annotating every data point will cost at the base reward, capped by
+    // `max_total_payout` when set. Halving and label multipliers are not forecast.
+    pub fn projected_total_payout(e: Env, project_id: u32) -> i128 {
+        let project = e
+            .storage()
+            .instance()
+            .get::<_, Project>(&DataKey::Project(project_id))
+            .unwrap();
+        let projected = project.data_point_count as i128
+            * project.config.required_annotations as i128
+            * project.config.reward_per_annotation;
+        let cap = project.config.max_total_payout;
+        if cap > 0 && projected > cap {
+            cap
+        } else {
+            projected
+        }
+    }
+
+    pub fn total_earnings_paid(e: Env, project_id: u32) -> i128 {
+        total_earnings(
+            &e.storage()
+                .instance()
+                .get::<_, Project>(&DataKey::Project(project_id))
+                .unwrap(),
+        )
+    }
+
+    pub fn token_decimals(e: Env, project_id: u32) -> u32 {
+        token::Client::new(&e, &get_token(&e, project_id)).decimals()
+    }
+
+    pub fn balance(e: Env, user: Address, project_id: u32) -> i128 {
+        let recipient = get_recipient(&e, project_id);
+        if user == recipient && peek_state(&e, project_id) == State::Annotating {
+            // only the recipient may read the pot through their own address
+            recipient.require_auth();
+            return get_balance(&e, project_id);
+        };
+
+        get_user_deposited(&e, &user, project_id)
+    }
+
+    pub fn contribute(e: Env, user: Address, token: Address, amount: i128, project_id: u32) -> u32 {
+        require_project(&e, project_id);
+        user.require_auth();
+        assert!(!is_paused(&e, project_id), "project paused");
+        assert!(amount > 0, "amount must be positive");
+        match get_state(&e, project_id) {
+            State::Funding => {}
+            State::Annotating => panic!("funding closed: annotating"),
+            State::Success => panic!("funding closed: completed"),
+            State::Expired => panic!("funding closed: expired"),
+        }
+        // Each project accepts exactly one token, callers name it so a contribution
+        // built against the wrong asset is rejected instead of silently rerouted.
+        let token_id = get_token(&e, project_id);
+        assert!(token == token_id, "wrong token");
+        let current_target_met = target_reached(&e, &token_id, project_id);
+
+        // In auto-refund mode only the gap to the target is pulled from the user,
+        // so the overshoot never leaves their account.
+        let project = e
+            .storage()
+            .instance()
+            .get::<_, Project>(&DataKey::Project(project_id))
+            .unwrap();
+        let accepted = accepted_amount(&project, amount);
+
+        let client = token::Client::new(&e, &token_id);
+        client.transfer(&user, &e.current_contract_address(), &accepted);
+        // the fee stays in the contract until the collector picks it up
+        let accepted = accepted - accrue_fee(&e, &token_id, accepted);
+
+        // aggregate-only projects keep just the total, so there is nothing to refund per user
+        let tracked = project.config.track_per_contributor;
+        if tracked {
+            let balance = get_user_deposited(&e, &user, project_id);
+            set_user_deposited(&e, &user, &(balance + accepted), project_id);
+        }
+
+        let mut project = e
+            .storage()
+            .instance()
+            .get::<_, Project>(&DataKey::Project(project_id))
+            .unwrap();
+        let matched = matched_amount(&project, accepted);
+        project.current_amount += accepted;
+        // receipts are numbered from 1 in deposit order
+        project.contribution_count += 1;
+        let receipt = project.contribution_count;
+        if tracked {
+            project.contributions.push_back(Contribution {
+                contributor: user.clone(),
+                amount: accepted,
+                timestamp: get_ledger_timestamp(&e),
+            });
+        }
+
+        // Matching funds come out of the sponsor's allowance and are credited to
+        // the sponsor as a contribution, so they are refunded like any other.
+        if matched > 0 {
+            let sponsor = project.config.sponsor.clone();
+            client.transfer_from(
+                &e.current_contract_address(),
+                &sponsor,
+                &e.current_contract_address(),
+                &matched,
             );
+            if tracked {
+                let sponsored = project
+                    .contributors_contribution_map
+                    .get(sponsor.clone())
+                    .unwrap_or(0);
+                project
+                    .contributors_contribution_map
+                    .set(sponsor.clone(), sponsored + matched);
+                project.contributions.push_back(Contribution {
+                    contributor: sponsor,
+                    amount: matched,
+                    timestamp: get_ledger_timestamp(&e),
+                });
+            }
+            project.matched_amount += matched;
+            project.current_amount += matched;
         }
-        let contributors_contribution_map: Map<Address, i128> = Map::new(&e);
-        let annotators_earnings_map: Map<Address, i128> = Map::new(&e);
-
-        let project = Project {
-            id: id,
-            name: name,
-            description: description,
-            recipient: recipient,
-            state: State::Funding,
-            started: get_ledger_timestamp(&e),
-            contributors_contribution_map: contributors_contribution_map,
-            annotators_earning_map: annotators_earnings_map,
-            deadline: deadline,
-            target_amount: target_amount,
-            current_amount: 0,
-            data_points: data_points,
-        };
-        e.storage().instance().set(&DataKey::Project(id), &project);
+        // the reward schedule runs from the deposit that completes funding
+        if project.current_amount >= project.target_amount {
+            project.annotating_since = Some(get_ledger_timestamp(&e));
+        }
+        e.storage()
+            .instance()
+            .set(&DataKey::Project(project_id), &project);
+        let total_raised: i128 = e
+            .storage()
+            .instance()
+            .get(&DataKey::TotalRaised)
+            .unwrap_or(0);
+        e.storage()
+            .instance()
+            .set(&DataKey::TotalRaised, &(total_raised + accepted + matched));
+
+        let contract_balance = get_balance(&e, project_id);
+
+        // emit events
+        events::pledged_amount_changed(&e, contract_balance);
+        if !current_target_met && target_reached(&e, &token_id, project_id) {
+            // only emit the target reached event once on the pledge that triggers target to be met
+            events::target_reached(&e, contract_balance, get_target_amount(&e, project_id));
+        }
+        receipt
+    }
+
+    pub fn contribution_count(e: Env, project_id: u32) -> u32 {
+        e.storage()
+            .instance()
+            .get::<_, Project>(&DataKey::Project(project_id))
+            .unwrap()
+            .contribution_count
+    }
+
+    pub fn contributor_count(e: Env, project_id: u32) -> u32 {
+        e.storage()
+            .instance()
+            .get::<_, Project>(&DataKey::Project(project_id))
+            .unwrap()
+            .contributors_contribution_map
+            .len()
+    }
+
+    pub fn avg_contribution(e: Env, project_id: u32) -> i128 {
+        let contributors = Self::contributor_count(e.clone(), project_id);
+        if contributors == 0 {
+            return 0;
+        }
+        get_balance(&e, project_id) / contributors as i128
+    }
+
+    pub fn contributions_since(e: Env, project_id: u32, since: u64) -> i128 {
+        let project = e
+            .storage()
+            .instance()
+            .get::<_, Project>(&DataKey::Project(project_id))
+            .unwrap();
+        let mut total: i128 = 0;
+        for contribution in project.contributions.iter() {
+            if contribution.timestamp > since {
+                total += contribution.amount;
+            }
+        }
+        total
+    }
+
+    pub fn top_up(e: Env, project_id: u32, from: Address, amount: i128) {
+        from.require_auth();
+        assert!(amount > 0, "amount must be positive");
+        assert!(
+            get_state(&e, project_id) == State::Annotating,
+            "project is not annotating"
+        );
+
+        let client = token::Client::new(&e, &get_token(&e, project_id));
+        client.transfer(&from, &e.current_contract_address(), &amount);
+
+        let mut project = e
+            .storage()
+            .instance()
+            .get::<_, Project>(&DataKey::Project(project_id))
+            .unwrap();
+        project.current_amount += amount;
+        e.storage()
+            .instance()
+            .set(&DataKey::Project(project_id), &project);
+
+        events::pledged_amount_changed(&e, get_balance(&e, project_id));
+    }
+
+    pub fn simulate_contribute(e: Env, project_id: u32, amount: i128) -> (u32, i128) {
+        let mut project = e
+            .storage()
+            .instance()
+            .get::<_, Project>(&DataKey::Project(project_id))
+            .unwrap();
+        project.state = compute_state(&e, &project);
+        if project.state == State::Funding && amount > 0 {
+            let accepted = accepted_amount(&project, amount);
+            // the same fee `contribute` would book
+            let accepted = accepted - contribution_fee(&e, accepted);
+            project.current_amount += accepted + matched_amount(&project, accepted);
+            project.state = compute_state(&e, &project);
+        }
+        (project.state as u32, project.current_amount)
+    }
+
+    pub fn get_name(e: Env, project_id: u32) -> String {
+        let project = e
+            .storage()
+            .instance()
+            .get::<_, Project>(&DataKey::Project(project_id))
+            .unwrap();
+        project.name
+    }
+
+    pub fn get_description(e: Env, project_id: u32) -> String {
+        let project = e
+            .storage()
+            .instance()
+            .get::<_, Project>(&DataKey::Project(project_id))
+            .unwrap();
+        project.description
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    pub fn submit(
+        e: Env,
+        to: Address,
+        data_point_cid: Symbol,
+        posy: u32,
+        posx: u32,
+        width: u32,
+        height: u32,
+        label: Symbol,
+        confidence: u32,
+        project_id: u32,
+    ) {
+        require_project(&e, project_id);
+        assert!(label != Symbol::new(&e, ""), "label cannot be empty");
+        record_annotation(
+            &e,
+            project_id,
+            data_point_cid,
+            Annotation {
+                annotator: to,
+                posx,
+                posy,
+                width,
+                height,
+                label,
+                confidence,
+                class_id: None,
+                pending: false,
+                reward: 0,
+            },
+        );
+    }
+
+    // Variant of `submit` for work signed off-chain by the project's trusted signer.
+    // The signature covers the XDR of `(to, project_id, cid, posx, posy, width, height, label)`.
+    #[allow(clippy::too_many_arguments)]
+    pub fn submit_signed(
+        e: Env,
+        to: Address,
+        data_point_cid: Symbol,
+        posx: u32,
+        posy: u32,
+        width: u32,
+        height: u32,
+        label: Symbol,
+        sig: BytesN<64>,
+        pubkey: BytesN<32>,
+        project_id: u32,
+    ) {
+        assert!(label != Symbol::new(&e, ""), "label cannot be empty");
+        let signer = get_config(&e, project_id).trusted_signer;
+        assert!(
+            signer != BytesN::from_array(&e, &[0; 32]),
+            "signed submissions not enabled"
+        );
+        assert!(pubkey == signer, "untrusted signer");
+        let payload = (
+            to.clone(),
+            project_id,
+            data_point_cid.clone(),
+            posx,
+            posy,
+            width,
+            height,
+            label.clone(),
+        )
+            .to_xdr(&e);
+        e.crypto().ed25519_verify(&pubkey, &payload, &sig);
+
+        record_annotation(
+            &e,
+            project_id,
+            data_point_cid,
+            Annotation {
+                annotator: to,
+                posx,
+                posy,
+                width,
+                height,
+                label,
+                confidence: 100,
+                class_id: None,
+                pending: false,
+                reward: 0,
+            },
+        );
+    }
+
+    // Variant of `submit` for projects labelled with integer class ids, the label is left empty.
+    #[allow(clippy::too_many_arguments)]
+    pub fn submit_classid(
+        e: Env,
+        to: Address,
+        data_point_cid: Symbol,
+        posx: u32,
+        posy: u32,
+        width: u32,
+        height: u32,
+        class_id: u32,
+        project_id: u32,
+    ) {
+        let num_classes = get_config(&e, project_id).num_classes;
+        assert!(num_classes > 0, "class ids not enabled");
+        assert!(class_id < num_classes, "class id out of range");
+        record_annotation(
+            &e,
+            project_id,
+            data_point_cid,
+            Annotation {
+                annotator: to,
+                posx,
+                posy,
+                width,
+                height,
+                label: Symbol::new(&e, ""),
+                confidence: 100,
+                class_id: Some(class_id),
+                pending: false,
+                reward: 0,
+            },
+        );
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    pub fn edit_annotation(
+        e: Env,
+        project_id: u32,
+        data_point_cid: Symbol,
+        annotator: Address,
+        posx: u32,
+        posy: u32,
+        width: u32,
+        height: u32,
+        label: Symbol,
+    ) {
+        annotator.require_auth();
+        assert!(label != Symbol::new(&e, ""), "label cannot be empty");
+        check_annotator(&e, project_id, &annotator);
+        assert!(
+            get_state(&e, project_id) == State::Annotating,
+            "project is not annotating"
+        );
+        let mut project = e
+            .storage()
+            .instance()
+            .get::<_, Project>(&DataKey::Project(project_id))
+            .unwrap();
+        let mut data_point = project.data_points.get(data_point_cid.clone()).unwrap();
+        assert!(!data_point.annotated, "data point finalized");
+
+        let mut index = None;
+        for (i, annotation) in data_point.annotations.iter().enumerate() {
+            if annotation.annotator == annotator {
+                index = Some(i as u32);
+                break;
+            }
+        }
+        let index = index.expect("no annotation to edit");
+
+        let mut annotation = data_point.annotations.get(index).unwrap();
+        // keep the replaced version around for review
+        data_point.history.push_back(annotation.clone());
+        annotation.posx = posx;
+        annotation.posy = posy;
+        annotation.width = width;
+        annotation.height = height;
+        annotation.label = label;
+        check_annotation(&e, project_id, &annotation);
+        data_point.annotations.set(index, annotation);
+        project.data_points.set(data_point_cid, data_point);
+        e.storage()
+            .instance()
+            .set(&DataKey::Project(project_id), &project);
+    }
+
+    pub fn reassign_annotation_reward(
+        e: Env,
+        project_id: u32,
+        data_point_cid: Symbol,
+        index: u32,
+        new_annotator: Address,
+    ) {
+        let state = get_state(&e, project_id);
+        let mut project = e
+            .storage()
+            .instance()
+            .get::<_, Project>(&DataKey::Project(project_id))
+            .unwrap();
+        let mut data_point = project.data_points.get(data_point_cid.clone()).unwrap();
+        let mut annotation = data_point
+            .annotations
+            .get(index)
+            .expect("index out of range");
+        let old_annotator = annotation.annotator.clone();
+        old_annotator.require_auth();
+        // rewards leave the contract right away unless batched, and batches pay out on close
+        let paid = !project.config.batch_rewards || state != State::Annotating;
+        assert!(!(annotation.reward > 0 && paid), "reward already paid");
+
+        let moved = annotation.reward;
+        let earnings = project
+            .annotators_earning_map
+            .get(old_annotator.clone())
+            .unwrap_or(0);
+        project
+            .annotators_earning_map
+            .set(old_annotator.clone(), earnings - moved);
+        let new_earnings = project
+            .annotators_earning_map
+            .get(new_annotator.clone())
+            .unwrap_or(0)
+            + moved;
+        project
+            .annotators_earning_map
+            .set(new_annotator.clone(), new_earnings);
+        if project.config.batch_rewards {
+            let pending = project
+                .pending_rewards
+                .get(old_annotator.clone())
+                .unwrap_or(0);
+            project
+                .pending_rewards
+                .set(old_annotator.clone(), pending - moved);
+            let new_pending = project
+                .pending_rewards
+                .get(new_annotator.clone())
+                .unwrap_or(0)
+                + moved;
+            project
+                .pending_rewards
+                .set(new_annotator.clone(), new_pending);
+        }
+
+        annotation.annotator = new_annotator.clone();
+        data_point.annotations.set(index, annotation);
+        project.data_points.set(data_point_cid, data_point);
+        e.storage()
+            .instance()
+            .set(&DataKey::Project(project_id), &project);
+        events::earnings_changed(&e, project_id, &old_annotator, earnings - moved);
+        events::earnings_changed(&e, project_id, &new_annotator, new_earnings);
+    }
+
+    pub fn get_annotations(e: Env, project_id: u32, data_point_cid: Symbol) -> Vec<Annotation> {
+        e.storage()
+            .instance()
+            .get::<_, Project>(&DataKey::Project(project_id))
+            .unwrap()
+            .data_points
+            .get(data_point_cid)
+            .unwrap()
+            .annotations
+    }
+
+    // Earlier versions of edited annotations, oldest first.
+    pub fn annotation_history(e: Env, project_id: u32, data_point_cid: Symbol) -> Vec<Annotation> {
+        e.storage()
+            .instance()
+            .get::<_, Project>(&DataKey::Project(project_id))
+            .unwrap()
+            .data_points
+            .get(data_point_cid)
+            .unwrap()
+            .history
+    }
+
+    pub fn annotations_with_label(
+        e: Env,
+        project_id: u32,
+        data_point_cid: Symbol,
+        label: Symbol,
+    ) -> Vec<Annotation> {
+        let mut matching: Vec<Annotation> = Vec::new(&e);
+        for annotation in Self::get_annotations(e.clone(), project_id, data_point_cid).iter() {
+            if annotation.label == label {
+                matching.push_back(annotation);
+            }
+        }
+        matching
+    }
+
+    pub fn get_annotation(
+        e: Env,
+        project_id: u32,
+        data_point_cid: Symbol,
+        index: u32,
+    ) -> Annotation {
+        e.storage()
+            .instance()
+            .get::<_, Project>(&DataKey::Project(project_id))
+            .unwrap()
+            .data_points
+            .get(data_point_cid)
+            .unwrap()
+            .annotations
+            .get(index)
+            .expect("index out of range")
+    }
+
+    pub fn consensus_label(e: Env, project_id: u32, data_point_cid: Symbol) -> Symbol {
+        let data_point = e
+            .storage()
+            .instance()
+            .get::<_, Project>(&DataKey::Project(project_id))
+            .unwrap()
+            .data_points
+            .get(data_point_cid)
+            .unwrap();
+        assert!(data_point.annotated, "data point not finalized");
+        data_point.consensus_label
+    }
+
+    // Ledger time the data point was finalized at, 0 while it is still open.
+    pub fn finalized_at(e: Env, project_id: u32, data_point_cid: Symbol) -> u64 {
+        e.storage()
+            .instance()
+            .get::<_, Project>(&DataKey::Project(project_id))
+            .unwrap()
+            .data_points
+            .get(data_point_cid)
+            .unwrap()
+            .finalized_at
+    }
+
+    pub fn all_annotated(e: Env, project_id: u32) -> bool {
+        let project = e
+            .storage()
+            .instance()
+            .get::<_, Project>(&DataKey::Project(project_id))
+            .unwrap();
+        is_fully_annotated(&project)
+    }
+
+    pub fn avg_annotations(e: Env, project_id: u32) -> u32 {
+        let project = e
+            .storage()
+            .instance()
+            .get::<_, Project>(&DataKey::Project(project_id))
+            .unwrap();
+        if project.data_points.is_empty() {
+            return 0;
+        }
+        let mut total: u32 = 0;
+        for data_point in project.data_points.values().iter() {
+            total += data_point.annotations.len();
+        }
+        total / project.data_points.len()
+    }
+
+    pub fn data_point_cids(e: Env, project_id: u32) -> Vec<Symbol> {
+        e.storage()
+            .instance()
+            .get::<_, Project>(&DataKey::Project(project_id))
+            .unwrap()
+            .data_points
+            .keys()
+    }
+
+    pub fn annotations_digest(e: Env, project_id: u32) -> BytesN<32> {
+        let project = e
+            .storage()
+            .instance()
+            .get::<_, Project>(&DataKey::Project(project_id))
+            .unwrap();
+        // data points iterate in cid order and annotations in submission order
+        let mut payload = Bytes::new(&e);
+        for (cid, data_point) in project.data_points.iter() {
+            payload.append(&cid.to_xdr(&e));
+            for annotation in data_point.annotations.iter() {
+                payload.append(&annotation.to_xdr(&e));
+            }
+        }
+        e.crypto().sha256(&payload).into()
+    }
+
+    pub fn pending_cids(e: Env, project_id: u32, limit: u32) -> Vec<Symbol> {
+        let project = e
+            .storage()
+            .instance()
+            .get::<_, Project>(&DataKey::Project(project_id))
+            .unwrap();
+        let mut pending: Vec<Symbol> = Vec::new(&e);
+        for (cid, data_point) in project.data_points.iter() {
+            if pending.len() >= limit {
+                break;
+            }
+            if !data_point.annotated {
+                pending.push_back(cid);
+            }
+        }
+        pending
+    }
+
+    pub fn stake(e: Env, annotator: Address, project_id: u32) {
+        annotator.require_auth();
+        let required_stake = get_config(&e, project_id).annotator_stake;
+        assert!(required_stake > 0, "staking disabled");
+        assert!(get_stake(&e, &annotator, project_id) == 0, "already staked");
+
+        let client = token::Client::new(&e, &get_token(&e, project_id));
+        client.transfer(&annotator, &e.current_contract_address(), &required_stake);
+
+        let mut project = e
+            .storage()
+            .instance()
+            .get::<_, Project>(&DataKey::Project(project_id))
+            .unwrap();
+        project
+            .annotator_stakes
+            .set(annotator.clone(), required_stake);
         e.storage()
             .instance()
-            .set(&DataKey::ProjectCount, &project_count);
+            .set(&DataKey::Project(project_id), &project);
+    }
+
+    pub fn get_stake(e: Env, annotator: Address, project_id: u32) -> i128 {
+        get_stake(&e, &annotator, project_id)
+    }
+
+    pub fn unstake(e: Env, annotator: Address, project_id: u32) {
+        annotator.require_auth();
+        let state = get_state(&e, project_id);
+        assert!(
+            state == State::Success || state == State::Expired,
+            "project still running"
+        );
+        let staked = get_stake(&e, &annotator, project_id);
+        assert!(staked > 0, "nothing staked");
 
-        let mut project_ids: Vec<u32> = e
+        let mut project = e
             .storage()
             .instance()
-            .get::<_, Vec<u32>>(&DataKey::ProjectIDs)
-            .unwrap_or(Vec::new(&e));
-        project_ids.push_back(id);
+            .get::<_, Project>(&DataKey::Project(project_id))
+            .unwrap();
+        project.annotator_stakes.set(annotator.clone(), 0);
         e.storage()
             .instance()
-            .set(&DataKey::ProjectIDs, &project_ids);
+            .set(&DataKey::Project(project_id), &project);
+        transfer(&e, &annotator, &staked, project_id);
     }
 
-    pub fn get_projects(e: Env) -> Vec<Project> {
-        let mut projects: Vec<Project> = Vec::new(&e);
-        let project_ids = get_project_ids(e.clone());
-        for project_id in project_ids.iter() {
-            let project = e
-                .storage()
+    // Flags an annotation as bad, for the recipient to settle with `resolve_dispute`.
+    pub fn open_dispute(
+        e: Env,
+        disputer: Address,
+        project_id: u32,
+        data_point_cid: Symbol,
+        index: u32,
+    ) {
+        disputer.require_auth();
+        let mut project = e
+            .storage()
+            .instance()
+            .get::<_, Project>(&DataKey::Project(project_id))
+            .unwrap();
+        project
+            .data_points
+            .get(data_point_cid.clone())
+            .unwrap()
+            .annotations
+            .get(index)
+            .expect("index out of range");
+        let dispute = (data_point_cid, index);
+        assert!(!project.disputes.contains(&dispute), "already disputed");
+        project.disputes.push_back(dispute);
+        e.storage()
+            .instance()
+            .set(&DataKey::Project(project_id), &project);
+    }
+
+    // Resolves an open dispute against an annotation. When upheld, the annotator
+    // loses `slash_bps` of their stake to the project pot and gets the rest back,
+    // so they can stake again.
+    pub fn resolve_dispute(
+        e: Env,
+        project_id: u32,
+        data_point_cid: Symbol,
+        index: u32,
+        upheld: bool,
+    ) {
+        let mut project = e
+            .storage()
+            .instance()
+            .get::<_, Project>(&DataKey::Project(project_id))
+            .unwrap();
+        project.recipient.require_auth();
+        let dispute = project
+            .disputes
+            .first_index_of((data_point_cid.clone(), index))
+            .expect("no open dispute");
+        project.disputes.remove(dispute);
+        if !upheld {
+            e.storage()
                 .instance()
-                .get::<_, Project>(&DataKey::Project(project_id.clone()))
-                .unwrap();
-            projects.push_back(project);
+                .set(&DataKey::Project(project_id), &project);
+            return;
         }
-        projects
-    }
 
-    pub fn deadline(e: Env, project_id: u32) -> u64 {
-        get_deadline(&e, project_id)
-    }
+        let annotator = project
+            .data_points
+            .get(data_point_cid)
+            .unwrap()
+            .annotations
+            .get(index)
+            .expect("index out of range")
+            .annotator;
+        let staked = project.annotator_stakes.get(annotator.clone()).unwrap_or(0);
+        let slashed = staked * project.config.slash_bps as i128 / 10_000;
 
-    pub fn state(e: Env, project_id: u32) -> u32 {
-        get_state(&e, project_id) as u32
+        project.annotator_stakes.set(annotator.clone(), 0);
+        project.current_amount += slashed;
+        e.storage()
+            .instance()
+            .set(&DataKey::Project(project_id), &project);
+        if staked > slashed {
+            transfer(&e, &annotator, &(staked - slashed), project_id);
+        }
+        events::pledged_amount_changed(&e, get_balance(&e, project_id));
     }
 
-    pub fn target(e: Env, project_id: u32) -> i128 {
-        get_target_amount(&e, project_id)
-    }
+    // Accepts a pending annotation on an `approval_required` project and releases its reward.
+    pub fn approve_annotation(e: Env, project_id: u32, data_point_cid: Symbol, index: u32) {
+        let mut project = e
+            .storage()
+            .instance()
+            .get::<_, Project>(&DataKey::Project(project_id))
+            .unwrap();
+        project.recipient.require_auth();
+        assert!(
+            get_state(&e, project_id) == State::Annotating,
+            "project is not annotating"
+        );
+        let mut data_point = project.data_points.get(data_point_cid.clone()).unwrap();
+        let mut annotation = data_point
+            .annotations
+            .get(index)
+            .expect("index out of range");
+        assert!(annotation.pending, "annotation not pending");
 
-    pub fn token(e: Env) -> Address {
-        e.current_contract_address()
+        let to = annotation.annotator.clone();
+        let now = get_ledger_timestamp(&e);
+        let reward = credit_reward(&e, &mut project, &to, &annotation.label, now);
+        annotation.pending = false;
+        annotation.reward = reward;
+        data_point.annotations.set(index, annotation);
+        refresh_data_point(
+            &e,
+            &mut data_point,
+            project.config.required_annotations,
+            now,
+        );
+        project.data_points.set(data_point_cid, data_point);
+        e.storage()
+            .instance()
+            .set(&DataKey::Project(project_id), &project);
+        annotation_accepted(&e, &project, &to, reward);
     }
 
-    pub fn balance(e: Env, user: Address, project_id: u32) -> i128 {
-        let recipient = get_recipient(&e, project_id);
-        if get_state(&e, project_id) == State::Annotating {
-            if user != recipient {
-                return 0;
-            };
-            return get_balance(&e, project_id);
-        };
+    // Discards a pending annotation, nothing is paid for it.
+    pub fn reject_annotation(e: Env, project_id: u32, data_point_cid: Symbol, index: u32) {
+        let mut project = e
+            .storage()
+            .instance()
+            .get::<_, Project>(&DataKey::Project(project_id))
+            .unwrap();
+        project.recipient.require_auth();
+        let mut data_point = project.data_points.get(data_point_cid.clone()).unwrap();
+        let annotation = data_point
+            .annotations
+            .get(index)
+            .expect("index out of range");
+        assert!(annotation.pending, "annotation not pending");
 
-        get_user_deposited(&e, &user, project_id)
+        data_point.annotations.remove(index);
+        // disputes are keyed by position, drop the rejected one's and move later ones down
+        let mut disputes = Vec::new(&e);
+        for (cid, disputed) in project.disputes.iter() {
+            if cid != data_point_cid {
+                disputes.push_back((cid, disputed));
+            } else if disputed > index {
+                disputes.push_back((cid, disputed - 1));
+            }
+        }
+        project.disputes = disputes;
+        project.data_points.set(data_point_cid, data_point);
+        e.storage()
+            .instance()
+            .set(&DataKey::Project(project_id), &project);
     }
 
-    pub fn contribute(e: Env, user: Address, amount: i128, project_id: u32) {
-        user.require_auth();
-        assert!(amount > 0, "amount must be positive");
+    pub fn finalize(e: Env, project_id: u32) {
+        let project = e
+            .storage()
+            .instance()
+            .get::<_, Project>(&DataKey::Project(project_id))
+            .unwrap();
+        project.recipient.require_auth();
+        assert!(project.config.batch_rewards, "rewards are not batched");
         assert!(
-            get_state(&e, project_id) == State::Funding,
-            "sale is not running"
+            get_state(&e, project_id) == State::Annotating,
+            "project is not annotating"
+        );
+        // a pot too small for another reward can't get the remaining data points annotated
+        assert!(
+            is_fully_annotated(&project) || project.current_amount < current_reward(&e, &project),
+            "not fully annotated"
         );
-        let token_id = e.current_contract_address();
-        let current_target_met = target_reached(&e, &token_id, project_id);
 
-        let balance = get_user_deposited(&e, &user, project_id);
-        set_user_deposited(&e, &user, &(balance + amount), project_id);
+        close_annotation(&e, project);
+    }
 
-        let client = token::Client::new(&e, &token_id);
-        client.transfer(&user, &e.current_contract_address(), &amount);
+    // Puts an annotator's unpaid batched rewards back into the pot as their own
+    // contribution, instead of having them paid out at close.
+    pub fn reinvest_earnings(e: Env, annotator: Address, project_id: u32) {
+        annotator.require_auth();
+        assert!(
+            get_state(&e, project_id) == State::Annotating,
+            "project is not annotating"
+        );
         let mut project = e
             .storage()
             .instance()
             .get::<_, Project>(&DataKey::Project(project_id))
             .unwrap();
-        let current_contributions = project
+        assert!(
+            project.config.track_per_contributor,
+            "per-contributor tracking disabled"
+        );
+        let amount = project.pending_rewards.get(annotator.clone()).unwrap_or(0);
+        assert!(amount > 0, "no earnings to reinvest");
+
+        // the reward turns into a deposit, so it no longer counts as earned
+        project.pending_rewards.remove(annotator.clone());
+        let earnings = project
+            .annotators_earning_map
+            .get(annotator.clone())
+            .unwrap_or(0)
+            - amount;
+        project
+            .annotators_earning_map
+            .set(annotator.clone(), earnings);
+        let balance = project
             .contributors_contribution_map
-            .get(user.clone())
+            .get(annotator.clone())
             .unwrap_or(0);
         project
             .contributors_contribution_map
-            .set(user.clone(), current_contributions + &amount);
+            .set(annotator.clone(), balance + amount);
+        project.contributions.push_back(Contribution {
+            contributor: annotator.clone(),
+            amount,
+            timestamp: get_ledger_timestamp(&e),
+        });
+        project.contribution_count += 1;
+        project.current_amount += amount;
         e.storage()
             .instance()
             .set(&DataKey::Project(project_id), &project);
+        events::earnings_changed(&e, project_id, &annotator, earnings);
+        events::pledged_amount_changed(&e, project.current_amount);
+    }
 
-        let contract_balance = get_balance(&e, project_id);
+    pub fn reduce_target(e: Env, project_id: u32, new_target: i128) {
+        let mut project = e
+            .storage()
+            .instance()
+            .get::<_, Project>(&DataKey::Project(project_id))
+            .unwrap();
+        project.recipient.require_auth();
+        assert!(
+            get_state(&e, project_id) == State::Funding,
+            "sale is not running"
+        );
+        assert!(new_target < project.target_amount, "target not reduced");
+        assert!(
+            project.data_point_count as i128 * project.config.reward_per_annotation <= new_target,
+            "target below work cost"
+        );
 
-        // emit events
-        events::pledged_amount_changed(&e, contract_balance);
-        if !current_target_met && target_reached(&e, &token_id, project_id) {
-            // only emit the target reached event once on the pledge that triggers target to be met
-            events::target_reached(&e, contract_balance, get_target_amount(&e, project_id));
+        let old_target = project.target_amount;
+        project.target_amount = new_target;
+        // as with a contribution, the reward schedule runs from the moment funding completes
+        if project.current_amount >= new_target {
+            project.annotating_since = Some(get_ledger_timestamp(&e));
         }
+        e.storage()
+            .instance()
+            .set(&DataKey::Project(project_id), &project);
+        events::target_reduced(&e, project_id, old_target, new_target);
     }
 
-    pub fn get_name(e: Env, project_id: u32) -> Symbol {
-        let project = e
+    pub fn extend_deadline(e: Env, project_id: u32, new_deadline: u64) {
+        let mut project = e
             .storage()
             .instance()
             .get::<_, Project>(&DataKey::Project(project_id))
             .unwrap();
-        project.name
+        project.recipient.require_auth();
+        assert!(
+            get_state(&e, project_id) == State::Funding,
+            "sale is not running"
+        );
+        assert!(new_deadline > project.deadline, "deadline not extended");
+        assert!(
+            project.config.annotation_deadline == 0
+                || project.config.annotation_deadline > new_deadline,
+            "annotation deadline before funding deadline"
+        );
+
+        let old_deadline = project.deadline;
+        project.deadline = new_deadline;
+        e.storage()
+            .instance()
+            .set(&DataKey::Project(project_id), &project);
+        events::deadline_extended(&e, project_id, old_deadline, new_deadline);
     }
 
-    pub fn get_description(e: Env, project_id: u32) -> Symbol {
-        let project = e
+    pub fn is_valid_cid(e: Env, cid: Symbol) -> bool {
+        valid_cid(&e, &cid)
+    }
+
+    pub fn add_data_points(e: Env, project_id: u32, data_point_cids: Vec<Symbol>) {
+        let mut project = e
             .storage()
             .instance()
             .get::<_, Project>(&DataKey::Project(project_id))
             .unwrap();
-        project.description
-    }
-
-    pub fn submit(
-        e: Env,
-        to: Address,
-        data_point_cid: Symbol,
-        posy: u32,
-        posx: u32,
-        width: u32,
-        height: u32,
-        label: Symbol,
-        project_id: u32,
-    ) {
-        to.require_auth();
-        let state = get_state(&e, project_id);
+        project.recipient.require_auth();
+        assert!(
+            get_state(&e, project_id) == State::Funding,
+            "sale is not running"
+        );
 
-        match state {
-            State::Funding => {
-                panic!("sale is still running")
+        for cid in data_point_cids.iter() {
+            assert!(valid_cid(&e, &cid), "invalid cid");
+            if project.data_points.contains_key(cid.clone()) {
+                continue;
             }
-            State::Annotating => {
-                // Do some checks to make sure the user has annotated.
+            project.data_points.set(
+                cid.clone(),
+                DataPoint {
+                    cid: cid.clone(),
+                    annotated: false,
+                    annotations: Vec::new(&e),
+                    consensus_label: Symbol::new(&e, ""),
+                    history: Vec::new(&e),
+                    finalized_at: 0,
+                },
+            );
+            project.data_point_count += 1;
+        }
+        assert!(
+            project.data_point_count as i128 * project.config.reward_per_annotation
+                <= project.target_amount,
+            "target below work cost"
+        );
+        e.storage()
+            .instance()
+            .set(&DataKey::Project(project_id), &project);
+    }
 
-                assert!(label != Symbol::new(&e, ""), "label cannot be empty");
-                let mut project = e
-                    .storage()
-                    .instance()
-                    .get::<_, Project>(&DataKey::Project(project_id))
-                    .unwrap();
-                let mut data_point = project.data_points.get(data_point_cid.clone()).unwrap();
-                data_point.annotated = true;
-                data_point.annotations.push_back(Annotation {
-                    annotator: to.clone(),
-                    posx: posx,
-                    posy: posy,
-                    width: width,
-                    height: height,
-                    label: label,
-                });
+    pub fn data_point_count(e: Env, project_id: u32) -> u32 {
+        e.storage()
+            .instance()
+            .get::<_, Project>(&DataKey::Project(project_id))
+            .unwrap()
+            .data_point_count
+    }
 
-                project.data_points.set(data_point_cid, data_point);
+    // Anyone can poke a project to apply a missed soft cap.
+    pub fn check_soft_cap(e: Env, project_id: u32) -> u32 {
+        get_state(&e, project_id) as u32
+    }
 
-                e.storage()
-                    .instance()
-                    .set(&DataKey::Project(project_id), &project);
-                transfer(&e, &to, &1);
-                // check balance after transfer and if it's 0, we change state.
-                get_state(&e, project_id);
+    pub fn check_quorum(e: Env, project_id: u32) -> bool {
+        if get_state(&e, project_id) != State::Annotating {
+            return false;
+        }
+        let project = e
+            .storage()
+            .instance()
+            .get::<_, Project>(&DataKey::Project(project_id))
+            .unwrap();
+        let quorum_bps = project.config.completion_quorum_bps;
+        if quorum_bps == 0 || project.data_points.is_empty() {
+            return false;
+        }
+
+        let mut annotated: u32 = 0;
+        for data_point in project.data_points.values().iter() {
+            if data_point.annotated {
+                annotated += 1;
             }
-            State::Success => {
-                // Do some checks to make sure the user has annotated.
+        }
+        if (annotated as u64) * 10_000 < (quorum_bps as u64) * (project.data_points.len() as u64) {
+            return false;
+        }
+
+        close_annotation(&e, project);
+        true
+    }
+
+    pub fn sweep_remainder(e: Env, project_id: u32) -> i128 {
+        let state = get_state(&e, project_id);
+        let project = e
+            .storage()
+            .instance()
+            .get::<_, Project>(&DataKey::Project(project_id))
+            .unwrap();
+        // an expired aggregate-only pot has nobody to refund, so it goes to the recipient
+        assert!(
+            state == State::Success
+                || (state == State::Expired && !project.config.track_per_contributor),
+            "project not successful"
+        );
+        project.recipient.require_auth();
+        // contributors settle first so the recipient can't sweep their share
+        assert!(refundable_total(&project) == 0, "refunds outstanding");
+        sweep(&e, project_id)
+    }
 
-                let balance = get_user_deposited(&e, &to, project_id);
-                set_user_deposited(&e, &to, &0, project_id);
-                transfer(&e, &to, &balance);
-                let token_id = e.current_contract_address();
-                let contract_balance = get_balance(&e, project_id);
-                events::pledged_amount_changed(&e, contract_balance);
+    pub fn claim_all_success(e: Env, recipient: Address) -> i128 {
+        recipient.require_auth();
+        let mut total: i128 = 0;
+        for project_id in get_project_ids(e.clone()).iter() {
+            if get_recipient(&e, project_id) != recipient
+                || get_state(&e, project_id) != State::Success
+            {
+                continue;
             }
-            State::Expired => {
-                panic!("Withdraw, expired")
+            let project = e
+                .storage()
+                .instance()
+                .get::<_, Project>(&DataKey::Project(project_id))
+                .unwrap();
+            if refundable_total(&project) == 0 {
+                total += sweep(&e, project_id);
             }
-        };
+        }
+        total
     }
 
     pub fn withdraw(e: Env, user: Address, project_id: u32) {
-        assert!(get_state(&e, project_id) == State::Expired, "not expired");
+        require_project(&e, project_id);
+        let state = get_state(&e, project_id);
+        let config = get_config(&e, project_id);
+        let early = state == State::Funding && config.early_refunds;
+        assert!(state == State::Expired || early, "not expired");
         user.require_auth();
         let balance = get_user_deposited(&e, &user, project_id);
-        set_user_deposited(&e, &user, &0, project_id);
-        transfer(&e, &user, &balance);
+        assert!(balance > 0, "nothing to withdraw");
+        if !early {
+            refund(&e, &user, balance, project_id);
+            return;
+        }
+
+        // leaving a running campaign costs a fee that is kept for the fee collector
+        let fee = balance * config.refund_fee_bps as i128 / 10_000;
+        credit_fees(&e, &get_token(&e, project_id), fee);
+        let mut project = e
+            .storage()
+            .instance()
+            .get::<_, Project>(&DataKey::Project(project_id))
+            .unwrap();
+        project.current_amount -= fee;
+        e.storage()
+            .instance()
+            .set(&DataKey::Project(project_id), &project);
+        refund(&e, &user, balance - fee, project_id);
+        events::pledged_amount_changed(&e, get_balance(&e, project_id));
+    }
+
+    pub fn withdraw_many(e: Env, user: Address, project_ids: Vec<u32>) -> i128 {
+        user.require_auth();
+        let mut total: i128 = 0;
+        for project_id in project_ids.iter() {
+            // deleted or unknown ids are skipped like running ones
+            if !e.storage().instance().has(&DataKey::Project(project_id)) {
+                continue;
+            }
+            if get_state(&e, project_id) != State::Expired {
+                continue;
+            }
+            let balance = get_user_deposited(&e, &user, project_id);
+            if balance > 0 {
+                refund(&e, &user, balance, project_id);
+                total += balance;
+            }
+        }
+        total
+    }
+
+    pub fn has_withdrawn(e: Env, project_id: u32, user: Address) -> bool {
+        e.storage()
+            .instance()
+            .get::<_, Project>(&DataKey::Project(project_id))
+            .unwrap()
+            .withdrawn
+            .get(user)
+            .unwrap_or(false)
     }
 }
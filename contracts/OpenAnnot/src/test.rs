@@ -1,125 +1,61 @@
 #![cfg(test)]
+extern crate std;
 
-use super::testutils::{register_test_contract as register_crowdfund, DataAnnotate};
+use super::testutils::{
+    register_reentrant_token, register_test_contract as register_crowdfund, DataAnnotate,
+    ReentrantTokenClient,
+};
+use crate::{
+    default_config, DataAnnotateClient, DataKey, LegacyState, Project, ProjectConfig, State,
+};
+use ed25519_dalek::{Keypair, Signer};
 use soroban_sdk::{
     testutils::{Address as AddressTestTrait, Events, Ledger},
-    token, vec, Address, Env, IntoVal, Symbol, Val, Vec,
+    token, vec,
+    xdr::{FromXdr, ToXdr},
+    Address, BytesN, Env, IntoVal, Map, String, Symbol, Val, Vec,
 };
 
-fn create_crowdfund_contract(
-    e: &Env,
-    recipient: &Address,
-    deadline: u64,
-    target_amount: &i128,
-    token: &Address,
-) -> (Address, DataAnnotate) {
-    let id = register_crowdfund(e);
-    let crowdfund = DataAnnotate::new(e, id.clone());
-    crowdfund
-        .client()
-        .initialize(recipient, &deadline, target_amount, token);
-    (id, crowdfund)
-}
-
 fn advance_ledger(e: &Env, delta: u64) {
     e.ledger().with_mut(|l| {
         l.timestamp += delta;
     });
 }
 
-struct Setup<'a> {
-    env: Env,
-    recipient: Address,
-    user1: Address,
-    user2: Address,
-    token: token::Client<'a>,
-    crowdfund: DataAnnotate,
-    crowdfund_id: Address,
-}
-
 fn create_token_contract<'a>(
     e: &Env,
     admin: &Address,
 ) -> (token::Client<'a>, token::StellarAssetClient<'a>) {
-    let contract_address = e.register_stellar_asset_contract(admin.clone());
+    let contract_address = e
+        .register_stellar_asset_contract_v2(admin.clone())
+        .address();
     (
         token::Client::new(e, &contract_address),
         token::StellarAssetClient::new(e, &contract_address),
     )
 }
 
-/// Sets up a crowdfund with -
-/// 1. Deadline 10 seconds from now.
-/// 2. Target amount of 15.
-/// 3. One deposit of 10 from user1.
-///
-impl Setup<'_> {
-    fn new() -> Self {
-        let e: Env = soroban_sdk::Env::default();
-        let recipient = Address::random(&e);
-        let user1 = Address::random(&e);
-        let user2 = Address::random(&e);
-
-        // the deadline is 10 seconds from now
-        let deadline = e.ledger().timestamp() + 10;
-        let target_amount: i128 = 15;
-
-        // Create the token contract
-        let token_admin = Address::random(&e);
-        let (token, token_admin) = create_token_contract(&e, &token_admin);
-
-        // Create the crowdfunding contract
-        let (crowdfund_id, crowdfund) =
-            create_crowdfund_contract(&e, &recipient, deadline, &target_amount, &token.address);
-
-        // Mint some tokens to work with
-        token_admin.mock_all_auths().mint(&user1, &10);
-        token_admin.mock_all_auths().mint(&user2, &8);
-
-        crowdfund.client().mock_all_auths().contribute(&user1, &10);
-
-        Self {
-            env: e,
-            recipient,
-            user1,
-            user2,
-            token,
-            crowdfund,
-            crowdfund_id,
-        }
-    }
-}
-
 #[test]
 fn test_expired() {
-    let setup = Setup::new();
+    let setup = ProjectSetup::new();
+    let project_id = setup.create_project(15, &["cid0"]);
+    setup.contribute(&setup.user1, 10, project_id);
     advance_ledger(&setup.env, 11);
+    assert_eq!(setup.client().state(&project_id), State::Expired as u32);
 
-    setup
-        .crowdfund
-        .client()
-        .mock_all_auths()
-        .submit(&setup.user1);
-
-    assert_eq!(setup.token.balance(&setup.user1), 10);
-    assert_eq!(setup.token.balance(&setup.crowdfund_id), 0);
+    setup.client().withdraw(&setup.user1, &project_id);
+    assert_eq!(setup.token.balance(&setup.user1), 100);
+    assert_eq!(setup.token.balance(&setup.annotate_id), 0);
 }
 
 #[test]
 fn test_events() {
-    let setup = Setup::new();
-    setup
-        .crowdfund
-        .client()
-        .mock_all_auths()
-        .contribute(&setup.user2, &5);
-    setup
-        .crowdfund
-        .client()
-        .mock_all_auths()
-        .contribute(&setup.user2, &3);
+    let setup = ProjectSetup::new();
+    let project_id = setup.create_project(15, &["cid0"]);
+    setup.contribute(&setup.user1, 10, project_id);
+    setup.contribute(&setup.user2, 5, project_id);
 
-    let mut crowd_fund_events: Vec<(Address, soroban_sdk::Vec<Val>, Val)> = vec![&setup.env];
+    let mut annotate_events: Vec<(Address, soroban_sdk::Vec<Val>, Val)> = vec![&setup.env];
 
     // there are SAC events emitted also, filter those away, not asserting that aspect
     setup
@@ -127,34 +63,28 @@ fn test_events() {
         .events()
         .all()
         .iter()
-        .filter(|event| event.0 == setup.crowdfund_id)
-        .for_each(|event| crowd_fund_events.push_back(event));
+        .filter(|event| event.0 == setup.annotate_id)
+        .for_each(|event| annotate_events.push_back(event));
 
     assert_eq!(
-        crowd_fund_events,
+        annotate_events,
         vec![
             &setup.env,
             (
-                setup.crowdfund_id.clone(),
+                setup.annotate_id.clone(),
                 (Symbol::new(&setup.env, "pledged_amount_changed"),).into_val(&setup.env),
                 10_i128.into_val(&setup.env)
             ),
             (
-                setup.crowdfund_id.clone(),
+                setup.annotate_id.clone(),
                 (Symbol::new(&setup.env, "pledged_amount_changed"),).into_val(&setup.env),
                 15_i128.into_val(&setup.env)
             ),
             (
-                // validate that this event only emitted once, ensuing deposits over the
-                // target before expiration, don't trigger this one again
-                setup.crowdfund_id.clone(),
+                // only the deposit that meets the target emits this one
+                setup.annotate_id.clone(),
                 (Symbol::new(&setup.env, "target_reached"),).into_val(&setup.env),
-                (15_i128, 15_i128).into_val(&setup.env)
-            ),
-            (
-                setup.crowdfund_id.clone(),
-                (Symbol::new(&setup.env, "pledged_amount_changed"),).into_val(&setup.env),
-                18_i128.into_val(&setup.env)
+                vec![&setup.env, 15_i128, 15_i128].into_val(&setup.env)
             ),
         ]
     );
@@ -162,131 +92,3509 @@ fn test_events() {
 
 #[test]
 fn test_success() {
-    let setup = Setup::new();
-    setup
-        .crowdfund
-        .client()
-        .mock_all_auths()
-        .contribute(&setup.user2, &5);
-
-    assert_eq!(setup.token.mock_all_auths().balance(&setup.user1), 0);
-    assert_eq!(setup.token.mock_all_auths().balance(&setup.user2), 3);
-    assert_eq!(
-        setup.token.mock_all_auths().balance(&setup.crowdfund_id),
-        15
-    );
+    let setup = ProjectSetup::new();
+    let project_id = setup.create_project(2, &["cid0", "cid1"]);
+    setup.contribute(&setup.user1, 2, project_id);
+    assert_eq!(setup.token.balance(&setup.annotate_id), 2);
 
-    advance_ledger(&setup.env, 10);
-    setup
-        .crowdfund
-        .client()
-        .mock_all_auths()
-        .submit(&setup.recipient);
+    setup.submit(&setup.user2, "cid0", "cat", project_id);
+    setup.submit(&setup.user2, "cid1", "cat", project_id);
 
-    assert_eq!(setup.token.mock_all_auths().balance(&setup.user1), 0);
-    assert_eq!(setup.token.mock_all_auths().balance(&setup.user2), 3);
-    assert_eq!(setup.token.mock_all_auths().balance(&setup.crowdfund_id), 0);
-    assert_eq!(setup.token.mock_all_auths().balance(&setup.recipient), 15);
+    // the pot is spent on rewards
+    assert_eq!(setup.client().state(&project_id), State::Success as u32);
+    assert_eq!(setup.token.balance(&setup.user1), 98);
+    assert_eq!(setup.token.balance(&setup.user2), 102);
+    assert_eq!(setup.token.balance(&setup.annotate_id), 0);
 }
 
 #[test]
 #[should_panic(expected = "sale is still running")]
 fn sale_still_running() {
-    let setup = Setup::new();
-    setup
-        .crowdfund
-        .client()
-        .mock_all_auths()
-        .submit(&setup.recipient);
+    let setup = ProjectSetup::new();
+    let project_id = setup.create_project(15, &["cid0"]);
+    setup.contribute(&setup.user1, 10, project_id);
+    setup.submit(&setup.user2, "cid0", "cat", project_id);
+}
+
+#[test]
+#[should_panic(expected = "funding closed: expired")]
+fn sale_not_running() {
+    let setup = ProjectSetup::new();
+    let project_id = setup.create_project(15, &["cid0"]);
+    setup.contribute(&setup.user1, 10, project_id);
+    advance_ledger(&setup.env, 11);
+
+    setup.contribute(&setup.user1, 1, project_id);
+}
+
+#[test]
+#[should_panic(expected = "Withdraw, expired")]
+fn sale_expired_submit_rejected() {
+    let setup = ProjectSetup::new();
+    let project_id = setup.create_project(15, &["cid0"]);
+    setup.contribute(&setup.user1, 10, project_id);
+    advance_ledger(&setup.env, 11);
+    setup.submit(&setup.user2, "cid0", "cat", project_id);
+}
+
+struct ProjectSetup<'a> {
+    env: Env,
+    recipient: Address,
+    user1: Address,
+    user2: Address,
+    token: token::Client<'a>,
+    token_admin: token::StellarAssetClient<'a>,
+    annotate: DataAnnotate,
+    annotate_id: Address,
+}
+
+/// Sets up the multi-project contract with -
+/// 1. A token with 100 minted to each of user1 and user2.
+/// 2. No projects, tests create the ones they need with `create_project`.
+///
+impl ProjectSetup<'_> {
+    fn new() -> Self {
+        let e: Env = soroban_sdk::Env::default();
+        e.mock_all_auths();
+
+        let recipient = Address::generate(&e);
+        let user1 = Address::generate(&e);
+        let user2 = Address::generate(&e);
+
+        let token_admin = Address::generate(&e);
+        let (token, token_admin) = create_token_contract(&e, &token_admin);
+
+        let annotate_id = register_crowdfund(&e);
+        let annotate = DataAnnotate::new(&e, annotate_id.clone());
+
+        token_admin.mint(&user1, &100);
+        token_admin.mint(&user2, &100);
+
+        Self {
+            env: e,
+            recipient,
+            user1,
+            user2,
+            token,
+            token_admin,
+            annotate,
+            annotate_id,
+        }
+    }
+
+    /// Sets up the contract with a single project built from `config` and returns its id.
+    fn with_config(
+        config: impl FnOnce(&Env) -> ProjectConfig,
+        cids: &[&str],
+        target_amount: i128,
+    ) -> (Self, u32) {
+        let setup = Self::new();
+        let config = config(&setup.env);
+        let project_id = setup.create_project_with_config(target_amount, cids, &config);
+        (setup, project_id)
+    }
+
+    fn client(&self) -> DataAnnotateClient<'_> {
+        self.annotate.client()
+    }
+
+    /// Creates a project with a deadline 10 seconds from now and returns its id.
+    fn create_project(&self, target_amount: i128, cids: &[&str]) -> u32 {
+        self.create_project_with_config(target_amount, cids, &default_config(&self.env))
+    }
+
+    fn create_project_with_config(
+        &self,
+        target_amount: i128,
+        cids: &[&str],
+        config: &ProjectConfig,
+    ) -> u32 {
+        self.create_project_for(&self.recipient, target_amount, cids, config)
+    }
+
+    fn create_project_for(
+        &self,
+        recipient: &Address,
+        target_amount: i128,
+        cids: &[&str],
+        config: &ProjectConfig,
+    ) -> u32 {
+        self.create_project_with_token(recipient, &self.token.address, target_amount, cids, config)
+    }
+
+    fn create_project_with_token(
+        &self,
+        recipient: &Address,
+        token: &Address,
+        target_amount: i128,
+        cids: &[&str],
+        config: &ProjectConfig,
+    ) -> u32 {
+        let e = &self.env;
+        let project_id = e.as_contract(&self.annotate_id, || {
+            e.storage()
+                .instance()
+                .get::<_, u32>(&DataKey::ProjectCount)
+                .unwrap_or(0)
+        });
+        let mut data_point_cids: Vec<Symbol> = vec![e];
+        for cid in cids {
+            data_point_cids.push_back(Symbol::new(e, cid));
+        }
+        self.client().initialize(
+            recipient,
+            &(e.ledger().timestamp() + 10),
+            &target_amount,
+            &data_point_cids,
+            &String::from_str(e, "project"),
+            &String::from_str(e, "description"),
+            token,
+            config,
+        );
+        project_id
+    }
+
+    fn contribute(&self, user: &Address, amount: i128, project_id: u32) {
+        self.client()
+            .contribute(user, &self.token.address, &amount, &project_id);
+    }
+
+    /// Submits a 10x10 box at the origin for `cid` labelled `label`.
+    fn submit(&self, annotator: &Address, cid: &str, label: &str, project_id: u32) {
+        self.submit_with_confidence(annotator, cid, label, 100, project_id);
+    }
+
+    fn submit_with_confidence(
+        &self,
+        annotator: &Address,
+        cid: &str,
+        label: &str,
+        confidence: u32,
+        project_id: u32,
+    ) {
+        self.client().submit(
+            annotator,
+            &Symbol::new(&self.env, cid),
+            &0,
+            &0,
+            &10,
+            &10,
+            &Symbol::new(&self.env, label),
+            &confidence,
+            &project_id,
+        );
+    }
+
+    fn set_current_amount(&self, project_id: u32, amount: i128) {
+        let e = &self.env;
+        e.as_contract(&self.annotate_id, || {
+            let mut project = e
+                .storage()
+                .instance()
+                .get::<_, Project>(&DataKey::Project(project_id))
+                .unwrap();
+            project.current_amount = amount;
+            e.storage()
+                .instance()
+                .set(&DataKey::Project(project_id), &project);
+        });
+    }
 }
 
 #[test]
-#[should_panic(expected = "sale was successful, only the recipient may withdraw")]
-fn sale_successful_only_recipient() {
-    let setup = Setup::new();
+fn test_name_and_description_support_free_text() {
+    let setup = ProjectSetup::new();
+    let e = &setup.env;
+
+    let name = String::from_str(e, "Street Scenes 2024");
+    let description = String::from_str(
+        e,
+        "Draw a tight box around every pedestrian, cyclist and car in the frame.",
+    );
+    setup.client().initialize(
+        &setup.recipient,
+        &(e.ledger().timestamp() + 10),
+        &15,
+        &vec![e, Symbol::new(e, "cid0")],
+        &name,
+        &description,
+        &setup.token.address,
+        &default_config(e),
+    );
+
+    assert_eq!(setup.client().get_name(&0), name);
+    assert_eq!(setup.client().get_description(&0), description);
+}
+
+#[test]
+fn test_pause_one_project_leaves_others_active() {
+    let setup = ProjectSetup::new();
+    let paused = setup.create_project(15, &["cid0"]);
+    let active = setup.create_project(15, &["cid0"]);
+
     setup
-        .crowdfund
         .client()
-        .mock_all_auths()
-        .contribute(&setup.user2, &5);
-    advance_ledger(&setup.env, 10);
+        .set_project_paused(&setup.recipient, &paused, &true);
+
+    assert!(setup.client().is_project_paused(&paused));
+    assert!(!setup.client().is_project_paused(&active));
+
+    setup.contribute(&setup.user1, 10, active);
+    assert_eq!(setup.client().balance(&setup.user1, &active), 10);
 
     setup
-        .crowdfund
         .client()
-        .mock_all_auths()
-        .submit(&setup.user1);
+        .set_project_paused(&setup.recipient, &paused, &false);
+    assert!(!setup.client().is_project_paused(&paused));
 }
 
 #[test]
-#[should_panic(expected = "sale was successful, only the recipient may withdraw")]
-fn sale_successful_non_recipient_still_denied_after_withdrawal() {
-    let setup = Setup::new();
-    setup
-        .crowdfund
-        .client()
-        .mock_all_auths()
-        .contribute(&setup.user2, &5);
-    advance_ledger(&setup.env, 10);
+fn test_admin_can_pause_project() {
+    let setup = ProjectSetup::new();
+    let admin = Address::generate(&setup.env);
+    let project_id = setup.create_project(15, &["cid0"]);
 
+    setup.client().set_admin(&admin);
     setup
-        .crowdfund
         .client()
-        .mock_all_auths()
-        .submit(&setup.recipient);
+        .set_project_paused(&admin, &project_id, &true);
+
+    assert!(setup.client().is_project_paused(&project_id));
+}
+
+#[test]
+#[should_panic(expected = "not authorized")]
+fn test_stranger_cannot_pause_project() {
+    let setup = ProjectSetup::new();
+    let project_id = setup.create_project(15, &["cid0"]);
+
     setup
-        .crowdfund
         .client()
-        .mock_all_auths()
-        .submit(&setup.user1);
+        .set_project_paused(&setup.user2, &project_id, &true);
 }
 
 #[test]
-#[should_panic(expected = "sale was successful, recipient has withdrawn funds already")]
-fn sale_successful_recipient_withdraws_only_once() {
-    let setup = Setup::new();
-    setup.crowdfund.client().contribute(&setup.user2, &5);
-    advance_ledger(&setup.env, 10);
+#[should_panic(expected = "project paused")]
+fn test_paused_project_rejects_contributions() {
+    let setup = ProjectSetup::new();
+    let project_id = setup.create_project(15, &["cid0"]);
 
     setup
-        .crowdfund
         .client()
-        .mock_all_auths()
-        .submit(&setup.recipient);
+        .set_project_paused(&setup.recipient, &project_id, &true);
+    setup.contribute(&setup.user1, 5, project_id);
+}
+
+#[test]
+#[should_panic(expected = "project paused")]
+fn test_paused_project_rejects_submissions() {
+    let setup = ProjectSetup::new();
+    let project_id = setup.create_project(15, &["cid0"]);
+
     setup
-        .crowdfund
         .client()
-        .mock_all_auths()
-        .submit(&setup.recipient);
+        .set_project_paused(&setup.recipient, &project_id, &true);
+    setup.submit(&setup.user2, "cid0", "cat", project_id);
 }
 
 #[test]
-#[should_panic(expected = "sale expired, the recipient may not withdraw")]
-fn sale_expired_recipient_not_allowed() {
-    let setup = Setup::new();
-    advance_ledger(&setup.env, 10);
+fn test_remaining_to_target() {
+    let setup = ProjectSetup::new();
+    let project_id = setup.create_project(15, &["cid0"]);
+
+    assert_eq!(setup.client().remaining_to_target(&project_id), 15);
+
+    // partially funded
+    setup.set_current_amount(project_id, 10);
+    assert_eq!(setup.client().remaining_to_target(&project_id), 5);
+
+    // fully funded
+    setup.set_current_amount(project_id, 15);
+    assert_eq!(setup.client().remaining_to_target(&project_id), 0);
+
+    // over-funded never reports a negative gap
+    setup.set_current_amount(project_id, 20);
+    assert_eq!(setup.client().remaining_to_target(&project_id), 0);
+}
+
+#[test]
+fn test_contribute_under_target() {
+    let setup = ProjectSetup::new();
+    let config = ProjectConfig {
+        auto_refund_overshoot: true,
+        ..default_config(&setup.env)
+    };
+    let project_id = setup.create_project_with_config(15, &["cid0"], &config);
+
+    setup.contribute(&setup.user1, 10, project_id);
+
+    assert_eq!(setup.client().balance(&setup.user1, &project_id), 10);
+    assert_eq!(setup.client().remaining_to_target(&project_id), 5);
+    assert_eq!(setup.token.balance(&setup.user1), 90);
+    assert_eq!(setup.token.balance(&setup.annotate_id), 10);
+}
+
+#[test]
+fn test_auto_refund_exact_fill() {
+    let setup = ProjectSetup::new();
+    let config = ProjectConfig {
+        auto_refund_overshoot: true,
+        ..default_config(&setup.env)
+    };
+    let project_id = setup.create_project_with_config(15, &["cid0"], &config);
+
+    setup.contribute(&setup.user1, 10, project_id);
+    setup.contribute(&setup.user2, 5, project_id);
+
+    assert_eq!(setup.client().remaining_to_target(&project_id), 0);
+    assert_eq!(setup.token.balance(&setup.user2), 95);
+    assert_eq!(setup.token.balance(&setup.annotate_id), 15);
+}
+
+#[test]
+fn test_auto_refund_overshoot() {
+    let setup = ProjectSetup::new();
+    let config = ProjectConfig {
+        auto_refund_overshoot: true,
+        ..default_config(&setup.env)
+    };
+    let project_id = setup.create_project_with_config(15, &["cid0"], &config);
+
+    setup.contribute(&setup.user1, 10, project_id);
+    setup.contribute(&setup.user2, 8, project_id);
+
+    // only the 5 needed to reach the target is kept, the other 3 stay with user2
+    assert_eq!(setup.token.balance(&setup.user2), 95);
+    assert_eq!(setup.token.balance(&setup.annotate_id), 15);
+    assert_eq!(setup.client().remaining_to_target(&project_id), 0);
+}
+
+#[test]
+fn test_overshoot_accepted_without_auto_refund() {
+    let setup = ProjectSetup::new();
+    let project_id = setup.create_project(15, &["cid0"]);
+
+    setup.contribute(&setup.user1, 10, project_id);
+    setup.contribute(&setup.user2, 8, project_id);
+
+    assert_eq!(setup.token.balance(&setup.user2), 92);
+    assert_eq!(setup.token.balance(&setup.annotate_id), 18);
+}
+
+fn staked_config(e: &Env) -> ProjectConfig {
+    ProjectConfig {
+        annotator_stake: 20,
+        slash_bps: 5_000,
+        ..default_config(e)
+    }
+}
+
+#[test]
+fn test_stake() {
+    let setup = ProjectSetup::new();
+    let project_id = setup.create_project_with_config(15, &["cid0"], &staked_config(&setup.env));
+
+    setup.client().stake(&setup.user2, &project_id);
+
+    assert_eq!(setup.client().get_stake(&setup.user2, &project_id), 20);
+    assert_eq!(setup.token.balance(&setup.user2), 80);
+    assert_eq!(setup.token.balance(&setup.annotate_id), 20);
+}
+
+#[test]
+#[should_panic(expected = "stake required")]
+fn test_submit_without_stake_rejected() {
+    let setup = ProjectSetup::new();
+    let project_id = setup.create_project_with_config(15, &["cid0"], &staked_config(&setup.env));
+    setup.contribute(&setup.user1, 15, project_id);
+
+    setup.submit(&setup.user2, "cid0", "cat", project_id);
+}
+
+#[test]
+fn test_submit_with_stake() {
+    let setup = ProjectSetup::new();
+    let project_id = setup.create_project_with_config(15, &["cid0"], &staked_config(&setup.env));
+    setup.contribute(&setup.user1, 15, project_id);
+    setup.client().stake(&setup.user2, &project_id);
+
+    setup.submit(&setup.user2, "cid0", "cat", project_id);
+
+    assert_eq!(setup.token.balance(&setup.user2), 81);
+}
+
+#[test]
+fn test_upheld_dispute_slashes_stake() {
+    let setup = ProjectSetup::new();
+    let project_id = setup.create_project_with_config(15, &["cid0"], &staked_config(&setup.env));
+    setup.contribute(&setup.user1, 15, project_id);
+    setup.client().stake(&setup.user2, &project_id);
+    setup.submit(&setup.user2, "cid0", "cat", project_id);
+
+    let cid = Symbol::new(&setup.env, "cid0");
+    setup
+        .client()
+        .open_dispute(&setup.user1, &project_id, &cid, &0);
+    setup
+        .client()
+        .resolve_dispute(&project_id, &cid, &0, &false);
+    assert_eq!(setup.client().get_stake(&setup.user2, &project_id), 20);
 
     setup
-        .crowdfund
         .client()
-        .mock_all_auths()
-        .submit(&setup.user1);
+        .open_dispute(&setup.user1, &project_id, &cid, &0);
+    setup.client().resolve_dispute(&project_id, &cid, &0, &true);
+    // half the stake is slashed, the other half goes back and the annotator may stake again
+    assert_eq!(setup.client().get_stake(&setup.user2, &project_id), 0);
+    assert_eq!(setup.token.balance(&setup.user2), 100 - 20 + 1 + 10);
+    let project = setup.client().get_projects().get(project_id).unwrap();
+    // 15 funded, 1 paid out for the annotation, 10 slashed in
+    assert_eq!(project.current_amount, 24);
+    setup.client().stake(&setup.user2, &project_id);
+    assert_eq!(setup.client().get_stake(&setup.user2, &project_id), 20);
+}
+
+#[test]
+#[should_panic(expected = "no open dispute")]
+fn test_resolve_dispute_requires_open_dispute() {
+    let setup = ProjectSetup::new();
+    let project_id = setup.create_project_with_config(15, &["cid0"], &staked_config(&setup.env));
+    setup.contribute(&setup.user1, 15, project_id);
+    setup.client().stake(&setup.user2, &project_id);
+    setup.submit(&setup.user2, "cid0", "cat", project_id);
+
     setup
-        .crowdfund
         .client()
-        .mock_all_auths()
-        .submit(&setup.recipient);
+        .resolve_dispute(&project_id, &Symbol::new(&setup.env, "cid0"), &0, &true);
 }
 
 #[test]
-#[should_panic(expected = "sale is not running")]
-fn sale_not_running() {
-    let setup = Setup::new();
-    advance_ledger(&setup.env, 10);
+fn test_get_annotation() {
+    let setup = ProjectSetup::new();
+    let project_id = setup.create_project(15, &["cid0"]);
+    setup.contribute(&setup.user1, 15, project_id);
+    setup.submit(&setup.user1, "cid0", "cat", project_id);
+    setup.submit(&setup.user2, "cid0", "dog", project_id);
+
+    let cid = Symbol::new(&setup.env, "cid0");
+    let first = setup.client().get_annotation(&project_id, &cid, &0);
+    assert_eq!(first.annotator, setup.user1);
+    assert_eq!(first.label, Symbol::new(&setup.env, "cat"));
+
+    let second = setup.client().get_annotation(&project_id, &cid, &1);
+    assert_eq!(second.annotator, setup.user2);
+    assert_eq!(second.label, Symbol::new(&setup.env, "dog"));
+}
+
+#[test]
+#[should_panic(expected = "index out of range")]
+fn test_get_annotation_out_of_range() {
+    let setup = ProjectSetup::new();
+    let project_id = setup.create_project(15, &["cid0"]);
+    setup.contribute(&setup.user1, 15, project_id);
+    setup.submit(&setup.user1, "cid0", "cat", project_id);
+    setup.submit(&setup.user2, "cid0", "dog", project_id);
 
     setup
-        .crowdfund
         .client()
-        .mock_all_auths()
-        .contribute(&setup.user1, &1);
+        .get_annotation(&project_id, &Symbol::new(&setup.env, "cid0"), &2);
+}
+
+#[test]
+fn test_migrate_from_legacy() {
+    let setup = ProjectSetup::new();
+    let e = &setup.env;
+    let admin = Address::generate(e);
+    setup.client().set_admin(&admin);
+    setup.create_project(15, &["cid0"]);
+
+    let legacy = LegacyState {
+        recipient: setup.recipient.clone(),
+        token: setup.token.address.clone(),
+        started: 5,
+        deadline: 1_000,
+        target_amount: 250,
+        data_point_cids: vec![e, Symbol::new(e, "cid0"), Symbol::new(e, "cid1")],
+    };
+    let project_id = setup.client().migrate_from_legacy(&legacy);
+    assert_eq!(project_id, 1);
+
+    let project = setup.client().get_projects().get(project_id).unwrap();
+    assert_eq!(project.id, project_id);
+    assert_eq!(project.recipient, legacy.recipient);
+    assert_eq!(project.token, legacy.token);
+    assert_eq!(project.started, 5);
+    assert_eq!(project.deadline, 1_000);
+    assert_eq!(project.target_amount, 250);
+    assert_eq!(project.current_amount, 0);
+    assert_eq!(project.data_points.keys(), legacy.data_point_cids);
+    assert_eq!(setup.client().state(&project_id), 0);
+}
+
+#[test]
+fn test_all_annotated() {
+    let setup = ProjectSetup::new();
+    let project_id = setup.create_project(15, &["cid0", "cid1"]);
+    setup.contribute(&setup.user1, 15, project_id);
+    assert!(!setup.client().all_annotated(&project_id));
+
+    setup.submit(&setup.user2, "cid0", "cat", project_id);
+    assert!(!setup.client().all_annotated(&project_id));
+
+    setup.submit(&setup.user2, "cid1", "dog", project_id);
+    assert!(setup.client().all_annotated(&project_id));
+}
+
+#[test]
+fn test_top_up_during_annotating() {
+    let setup = ProjectSetup::new();
+    let project_id = setup.create_project(2, &["cid0", "cid1"]);
+    setup.contribute(&setup.user1, 2, project_id);
+    setup.submit(&setup.user2, "cid0", "cat", project_id);
+
+    setup.token_admin.mint(&setup.recipient, &10);
+    setup.client().top_up(&project_id, &setup.recipient, &2);
+    assert_eq!(setup.client().state(&project_id), 1);
+    assert_eq!(setup.token.balance(&setup.recipient), 8);
+
+    // the original pot only covered two rewards, the top up pays for a second pass
+    setup.submit(&setup.user2, "cid1", "cat", project_id);
+    setup.submit(&setup.user1, "cid0", "cat", project_id);
+    setup.submit(&setup.user1, "cid1", "cat", project_id);
+    assert_eq!(setup.token.balance(&setup.user2), 102);
+    assert_eq!(setup.token.balance(&setup.user1), 100);
+    assert_eq!(setup.token.balance(&setup.annotate_id), 0);
+    assert_eq!(setup.client().state(&project_id), 2);
+}
+
+#[test]
+#[should_panic(expected = "project is not annotating")]
+fn test_top_up_rejected_while_funding() {
+    let setup = ProjectSetup::new();
+    let project_id = setup.create_project(15, &["cid0"]);
+
+    setup.client().top_up(&project_id, &setup.user1, &5);
+}
+
+#[test]
+fn test_earnings_changed_events() {
+    let setup = ProjectSetup::new();
+    let project_id = setup.create_project(2, &["cid0", "cid1"]);
+    setup.contribute(&setup.user1, 2, project_id);
+    setup.submit(&setup.user2, "cid0", "cat", project_id);
+    setup.submit(&setup.user2, "cid1", "dog", project_id);
+
+    let e = &setup.env;
+    let topics: Vec<Val> = (
+        Symbol::new(e, "earnings_changed"),
+        project_id,
+        setup.user2.clone(),
+    )
+        .into_val(e);
+    let mut earnings_events: Vec<(Address, soroban_sdk::Vec<Val>, Val)> = vec![e];
+    e.events()
+        .all()
+        .iter()
+        .filter(|event| event.0 == setup.annotate_id && event.1 == topics)
+        .for_each(|event| earnings_events.push_back(event));
+
+    // the payload carries the cumulative earnings, not the single reward
+    assert_eq!(
+        earnings_events,
+        vec![
+            e,
+            (
+                setup.annotate_id.clone(),
+                topics.clone(),
+                1_i128.into_val(e)
+            ),
+            (setup.annotate_id.clone(), topics, 2_i128.into_val(e)),
+        ]
+    );
+}
+
+#[test]
+fn test_get_project_with_and_without_annotations() {
+    let setup = ProjectSetup::new();
+    let project_id = setup.create_project(15, &["cid0", "cid1"]);
+    setup.contribute(&setup.user1, 15, project_id);
+    setup.submit(&setup.user2, "cid0", "cat", project_id);
+    setup.submit(&setup.user1, "cid0", "dog", project_id);
+
+    let cid0 = Symbol::new(&setup.env, "cid0");
+    let cid1 = Symbol::new(&setup.env, "cid1");
+
+    let full = setup.client().get_project(&project_id, &true);
+    assert_eq!(
+        full.data_points
+            .get(cid0.clone())
+            .unwrap()
+            .annotations
+            .len(),
+        2
+    );
+    assert!(full.data_points.get(cid0.clone()).unwrap().annotated);
+
+    let stripped = setup.client().get_project(&project_id, &false);
+    let data_point = stripped.data_points.get(cid0).unwrap();
+    assert_eq!(data_point.annotations.len(), 0);
+    assert!(data_point.annotated);
+    assert!(!stripped.data_points.get(cid1).unwrap().annotated);
+    assert_eq!(stripped.id, full.id);
+    assert_eq!(stripped.current_amount, full.current_amount);
+}
+
+#[test]
+fn test_balance_recipient_branch_requires_auth() {
+    let setup = ProjectSetup::new();
+    let project_id = setup.create_project(15, &["cid0"]);
+    setup.contribute(&setup.user1, 10, project_id);
+    setup.contribute(&setup.user2, 5, project_id);
+
+    assert_eq!(setup.client().balance(&setup.recipient, &project_id), 15);
+    let auths = setup.env.auths();
+    assert_eq!(auths.len(), 1);
+    assert_eq!(auths[0].0, setup.recipient);
+
+    // everyone else reads their own deposit without signing anything
+    assert_eq!(setup.client().balance(&setup.user1, &project_id), 10);
+    assert!(setup.env.auths().is_empty());
+}
+
+#[test]
+fn test_projects_of_recipient() {
+    let setup = ProjectSetup::new();
+    let other = Address::generate(&setup.env);
+    let config = default_config(&setup.env);
+    setup.create_project(15, &["cid0"]);
+    setup.create_project_for(&other, 15, &["cid0"], &config);
+    setup.create_project(15, &["cid0"]);
+
+    assert_eq!(
+        setup.client().projects_of(&setup.recipient),
+        vec![&setup.env, 0, 2]
+    );
+    assert_eq!(setup.client().projects_of(&other), vec![&setup.env, 1]);
+    assert_eq!(
+        setup.client().projects_of(&setup.user1),
+        Vec::<u32>::new(&setup.env)
+    );
+}
+
+#[test]
+fn test_contributed_projects() {
+    let setup = ProjectSetup::new();
+    let first = setup.create_project(15, &["cid0"]);
+    let second = setup.create_project(15, &["cid0"]);
+    let third = setup.create_project(15, &["cid0"]);
+
+    setup.contribute(&setup.user1, 5, first);
+    setup.contribute(&setup.user2, 5, second);
+    setup.contribute(&setup.user1, 5, third);
+
+    assert_eq!(
+        setup.client().contributed_projects(&setup.user1),
+        vec![&setup.env, first, third]
+    );
+    assert_eq!(
+        setup.client().contributed_projects(&setup.user2),
+        vec![&setup.env, second]
+    );
+}
+
+fn sponsored_setup(ratio_bps: u32, cap: i128) -> (ProjectSetup<'static>, Address, u32) {
+    let (setup, project_id) = ProjectSetup::with_config(
+        |e| ProjectConfig {
+            sponsor: Address::generate(e),
+            match_ratio_bps: ratio_bps,
+            match_cap: cap,
+            ..default_config(e)
+        },
+        &["cid0"],
+        30,
+    );
+    let sponsor = setup
+        .client()
+        .get_project(&project_id, &false)
+        .config
+        .sponsor;
+    setup.token_admin.mint(&sponsor, &100);
+    setup.token.approve(
+        &sponsor,
+        &setup.annotate_id,
+        &100,
+        &(setup.env.ledger().sequence() + 100),
+    );
+    (setup, sponsor, project_id)
+}
+
+#[test]
+fn test_matching_contribution() {
+    let (setup, sponsor, project_id) = sponsored_setup(5_000, 50);
+
+    setup.contribute(&setup.user1, 10, project_id);
+
+    assert_eq!(setup.token.balance(&sponsor), 95);
+    assert_eq!(setup.client().balance(&sponsor, &project_id), 5);
+    assert_eq!(setup.client().remaining_to_target(&project_id), 15);
+    assert_eq!(setup.token.balance(&setup.annotate_id), 15);
+}
+
+#[test]
+fn test_matching_respects_cap() {
+    let (setup, sponsor, project_id) = sponsored_setup(5_000, 6);
+
+    setup.contribute(&setup.user1, 10, project_id);
+    setup.contribute(&setup.user2, 4, project_id);
+
+    // the second match would be 2 but only 1 is left under the cap
+    assert_eq!(setup.token.balance(&sponsor), 94);
+    assert_eq!(setup.client().balance(&sponsor, &project_id), 6);
+    assert_eq!(setup.client().remaining_to_target(&project_id), 10);
+}
+
+#[test]
+fn test_zero_match_ratio_is_noop() {
+    let (setup, sponsor, project_id) = sponsored_setup(0, 50);
+
+    setup.contribute(&setup.user1, 10, project_id);
+
+    assert_eq!(setup.token.balance(&sponsor), 100);
+    assert_eq!(setup.client().balance(&sponsor, &project_id), 0);
+    assert_eq!(setup.client().remaining_to_target(&project_id), 20);
+}
+
+#[test]
+fn test_pending_cids() {
+    let setup = ProjectSetup::new();
+    let project_id = setup.create_project(15, &["cid0", "cid1", "cid2", "cid3", "cid4"]);
+    setup.contribute(&setup.user1, 15, project_id);
+    setup.submit(&setup.user2, "cid1", "cat", project_id);
+    setup.submit(&setup.user2, "cid3", "cat", project_id);
+
+    let e = &setup.env;
+    assert_eq!(
+        setup.client().pending_cids(&project_id, &10),
+        vec![
+            e,
+            Symbol::new(e, "cid0"),
+            Symbol::new(e, "cid2"),
+            Symbol::new(e, "cid4")
+        ]
+    );
+    assert_eq!(
+        setup.client().pending_cids(&project_id, &2),
+        vec![e, Symbol::new(e, "cid0"), Symbol::new(e, "cid2")]
+    );
+}
+
+#[test]
+fn test_leftover_below_reward_reaches_success() {
+    let setup = ProjectSetup::new();
+    let config = ProjectConfig {
+        reward_per_annotation: 5,
+        ..default_config(&setup.env)
+    };
+    let project_id = setup.create_project_with_config(13, &["cid0", "cid1"], &config);
+    setup.contribute(&setup.user1, 13, project_id);
+
+    setup.submit(&setup.user2, "cid0", "cat", project_id);
+    assert_eq!(setup.client().state(&project_id), 1);
+    setup.submit(&setup.user2, "cid1", "cat", project_id);
+
+    // 3 left can't pay a reward of 5
+    assert_eq!(setup.client().state(&project_id), 2);
+    assert_eq!(setup.token.balance(&setup.user2), 110);
+
+    // the 3 left go back to the contributor before anything can be swept
+    setup.submit(&setup.user1, "cid0", "cat", project_id);
+    assert_eq!(setup.token.balance(&setup.user1), 90);
+    assert_eq!(setup.client().sweep_remainder(&project_id), 0);
+    assert_eq!(setup.token.balance(&setup.annotate_id), 0);
+}
+
+#[test]
+fn test_simulate_contribute() {
+    let setup = ProjectSetup::new();
+    let project_id = setup.create_project(15, &["cid0"]);
+    setup.contribute(&setup.user1, 10, project_id);
+
+    assert_eq!(
+        setup.client().simulate_contribute(&project_id, &3),
+        (State::Funding as u32, 13)
+    );
+    assert_eq!(
+        setup.client().simulate_contribute(&project_id, &5),
+        (State::Annotating as u32, 15)
+    );
+
+    // nothing moved and nothing transitioned
+    let project = setup.client().get_project(&project_id, &false);
+    assert!(project.state == State::Funding);
+    assert_eq!(project.current_amount, 10);
+    assert_eq!(setup.token.balance(&setup.annotate_id), 10);
+    assert_eq!(setup.token.balance(&setup.user2), 100);
+}
+
+#[test]
+fn test_target_covers_work_cost() {
+    let setup = ProjectSetup::new();
+    let config = ProjectConfig {
+        reward_per_annotation: 5,
+        ..default_config(&setup.env)
+    };
+
+    let project_id = setup.create_project_with_config(15, &["cid0", "cid1", "cid2"], &config);
+    assert_eq!(setup.client().target(&project_id), 15);
+}
+
+#[test]
+#[should_panic(expected = "target below work cost")]
+fn test_target_below_work_cost() {
+    let setup = ProjectSetup::new();
+    let config = ProjectConfig {
+        reward_per_annotation: 5,
+        ..default_config(&setup.env)
+    };
+
+    setup.create_project_with_config(14, &["cid0", "cid1", "cid2"], &config);
+}
+
+#[test]
+fn test_token_decimals() {
+    let setup = ProjectSetup::new();
+    let project_id = setup.create_project(15, &["cid0"]);
+
+    // Stellar asset contracts always use 7 decimals
+    assert_eq!(setup.token.decimals(), 7);
+    assert_eq!(setup.client().token_decimals(&project_id), 7);
+}
+
+fn annotate_all(setup: &ProjectSetup, project_id: u32) {
+    setup.contribute(&setup.user1, 15, project_id);
+    setup.submit(&setup.user1, "cid0", "cat", project_id);
+    setup.submit(&setup.user2, "cid1", "cat", project_id);
+    setup.submit(&setup.user2, "cid2", "dog", project_id);
+}
+
+#[test]
+fn test_finalize_matches_incremental_rewards() {
+    let incremental = ProjectSetup::new();
+    let config = ProjectConfig {
+        reward_per_annotation: 5,
+        ..default_config(&incremental.env)
+    };
+    let project_id = incremental.create_project_with_config(15, &["cid0", "cid1", "cid2"], &config);
+    annotate_all(&incremental, project_id);
+
+    let batched = ProjectSetup::new();
+    let config = ProjectConfig {
+        reward_per_annotation: 5,
+        batch_rewards: true,
+        ..default_config(&batched.env)
+    };
+    let batched_id = batched.create_project_with_config(15, &["cid0", "cid1", "cid2"], &config);
+    annotate_all(&batched, batched_id);
+
+    // nothing is paid until the recipient finalizes
+    assert_eq!(batched.token.balance(&batched.user2), 100);
+    assert_eq!(batched.token.balance(&batched.annotate_id), 15);
+    assert_eq!(batched.client().state(&batched_id), 1);
+
+    batched.client().finalize(&batched_id);
+
+    assert_eq!(batched.client().state(&batched_id), 2);
+    assert_eq!(incremental.client().state(&project_id), 2);
+    assert_eq!(
+        batched.token.balance(&batched.user1),
+        incremental.token.balance(&incremental.user1)
+    );
+    assert_eq!(
+        batched.token.balance(&batched.user2),
+        incremental.token.balance(&incremental.user2)
+    );
+    assert_eq!(batched.token.balance(&batched.user2), 110);
+    assert_eq!(batched.token.balance(&batched.annotate_id), 0);
+}
+
+#[test]
+#[should_panic(expected = "not fully annotated")]
+fn test_finalize_requires_full_annotation() {
+    let setup = ProjectSetup::new();
+    let config = ProjectConfig {
+        batch_rewards: true,
+        ..default_config(&setup.env)
+    };
+    let project_id = setup.create_project_with_config(15, &["cid0", "cid1"], &config);
+    setup.contribute(&setup.user1, 15, project_id);
+    setup.submit(&setup.user2, "cid0", "cat", project_id);
+
+    setup.client().finalize(&project_id);
+}
+
+#[test]
+fn test_finalize_once_pot_is_drained() {
+    let (setup, project_id) = ProjectSetup::with_config(
+        |e| ProjectConfig {
+            batch_rewards: true,
+            ..multiplier_config(e, 300)
+        },
+        &["cid0", "cid1", "cid2", "cid3"],
+        15,
+    );
+    setup.contribute(&setup.user1, 15, project_id);
+    setup.submit(&setup.user2, "cid0", "hard", project_id);
+    setup.submit(&setup.user2, "cid1", "hard", project_id);
+    setup.submit(&setup.user2, "cid2", "cat", project_id);
+    // the pot is spent with a data point still open, so no one can finish the work
+    assert!(setup
+        .client()
+        .try_submit(
+            &setup.user2,
+            &Symbol::new(&setup.env, "cid3"),
+            &0,
+            &0,
+            &10,
+            &10,
+            &Symbol::new(&setup.env, "cat"),
+            &100,
+            &project_id,
+        )
+        .is_err());
+
+    setup.client().finalize(&project_id);
+    assert_eq!(setup.client().state(&project_id), State::Success as u32);
+    // 6 + 6 + 2 paid out, the last token is left for the recipient
+    assert_eq!(setup.token.balance(&setup.user2), 114);
+    assert_eq!(setup.token.balance(&setup.annotate_id), 1);
+}
+
+#[test]
+fn test_avg_annotations() {
+    let setup = ProjectSetup::new();
+    let empty = setup.create_project(15, &[]);
+    assert_eq!(setup.client().avg_annotations(&empty), 0);
+
+    let project_id = setup.create_project(15, &["cid0", "cid1", "cid2"]);
+    setup.contribute(&setup.user1, 15, project_id);
+    for cid in ["cid0", "cid0", "cid0", "cid1", "cid1", "cid2", "cid2"] {
+        setup.submit(&setup.user2, cid, "cat", project_id);
+    }
+
+    // 7 annotations over 3 data points rounds down
+    assert_eq!(setup.client().avg_annotations(&project_id), 2);
+}
+
+#[test]
+#[should_panic(expected = "wrong token")]
+fn test_contribute_with_wrong_token() {
+    let setup = ProjectSetup::new();
+    let project_id = setup.create_project(15, &["cid0"]);
+    let (other_token, other_admin) = create_token_contract(&setup.env, &setup.recipient);
+    other_admin.mint(&setup.user1, &10);
+
+    setup
+        .client()
+        .contribute(&setup.user1, &other_token.address, &10, &project_id);
+}
+
+#[test]
+fn test_data_point_cids() {
+    let setup = ProjectSetup::new();
+    let project_id = setup.create_project(15, &["cid0", "cid1", "cid2"]);
+
+    let e = &setup.env;
+    assert_eq!(
+        setup.client().data_point_cids(&project_id),
+        vec![
+            e,
+            Symbol::new(e, "cid0"),
+            Symbol::new(e, "cid1"),
+            Symbol::new(e, "cid2")
+        ]
+    );
+}
+
+#[test]
+#[should_panic(expected = "annotator banned")]
+fn test_banned_annotator_cannot_submit() {
+    let setup = ProjectSetup::new();
+    setup.client().set_admin(&Address::generate(&setup.env));
+    let project_id = setup.create_project(15, &["cid0"]);
+    setup.contribute(&setup.user1, 15, project_id);
+
+    setup.client().ban_annotator(&setup.user2);
+    setup.submit(&setup.user2, "cid0", "cat", project_id);
+}
+
+#[test]
+fn test_unbanned_annotator_can_submit() {
+    let setup = ProjectSetup::new();
+    setup.client().set_admin(&Address::generate(&setup.env));
+    let project_id = setup.create_project(15, &["cid0"]);
+    setup.contribute(&setup.user1, 15, project_id);
+
+    setup.client().ban_annotator(&setup.user2);
+    setup.client().unban_annotator(&setup.user2);
+    setup.submit(&setup.user2, "cid0", "cat", project_id);
+
+    assert_eq!(setup.token.balance(&setup.user2), 101);
+}
+
+#[test]
+fn test_ban_requires_admin_auth() {
+    let setup = ProjectSetup::new();
+    let admin = Address::generate(&setup.env);
+    setup.client().set_admin(&admin);
+
+    setup.client().ban_annotator(&setup.user2);
+    let auths = setup.env.auths();
+    assert_eq!(auths.len(), 1);
+    assert_eq!(auths[0].0, admin);
+
+    setup.client().unban_annotator(&setup.user2);
+    let auths = setup.env.auths();
+    assert_eq!(auths.len(), 1);
+    assert_eq!(auths[0].0, admin);
+}
+
+fn quorum_setup() -> (ProjectSetup<'static>, u32) {
+    let (setup, project_id) = ProjectSetup::with_config(
+        |e| ProjectConfig {
+            completion_quorum_bps: 8_000,
+            ..default_config(e)
+        },
+        &["cid0", "cid1", "cid2", "cid3", "cid4"],
+        15,
+    );
+    setup.contribute(&setup.user1, 15, project_id);
+    (setup, project_id)
+}
+
+#[test]
+fn test_quorum_not_met() {
+    let (setup, project_id) = quorum_setup();
+    setup.submit(&setup.user2, "cid0", "cat", project_id);
+    setup.submit(&setup.user2, "cid1", "cat", project_id);
+    setup.submit(&setup.user2, "cid2", "cat", project_id);
+
+    // 3 of 5 is 60%
+    assert!(!setup.client().check_quorum(&project_id));
+    assert_eq!(setup.client().state(&project_id), 1);
+}
+
+#[test]
+fn test_quorum_met() {
+    let (setup, project_id) = quorum_setup();
+    setup.submit(&setup.user2, "cid0", "cat", project_id);
+    setup.submit(&setup.user2, "cid1", "cat", project_id);
+    setup.submit(&setup.user2, "cid2", "cat", project_id);
+    setup.submit(&setup.user2, "cid3", "cat", project_id);
+
+    // 4 of 5 is exactly 80%
+    assert!(setup.client().check_quorum(&project_id));
+    assert_eq!(setup.client().state(&project_id), 2);
+}
+
+#[test]
+fn test_annotations_digest() {
+    let setup = ProjectSetup::new();
+    let project_id = setup.create_project(15, &["cid0", "cid1"]);
+    setup.contribute(&setup.user1, 15, project_id);
+    setup.submit(&setup.user2, "cid0", "cat", project_id);
+
+    let digest = setup.client().annotations_digest(&project_id);
+    assert_eq!(setup.client().annotations_digest(&project_id), digest);
+
+    setup.submit(&setup.user2, "cid1", "dog", project_id);
+    let updated = setup.client().annotations_digest(&project_id);
+    assert_ne!(updated, digest);
+    assert_eq!(setup.client().annotations_digest(&project_id), updated);
+}
+
+#[test]
+fn test_contribution_receipts() {
+    let setup = ProjectSetup::new();
+    let project_id = setup.create_project(15, &["cid0"]);
+    assert_eq!(setup.client().contribution_count(&project_id), 0);
+
+    let token = &setup.token.address;
+    let first = setup
+        .client()
+        .contribute(&setup.user1, token, &2, &project_id);
+    let second = setup
+        .client()
+        .contribute(&setup.user1, token, &3, &project_id);
+    let third = setup
+        .client()
+        .contribute(&setup.user2, token, &4, &project_id);
+
+    assert_eq!((first, second, third), (1, 2, 3));
+    assert_eq!(setup.client().contribution_count(&project_id), 3);
+}
+
+#[test]
+fn test_annotation_confidence() {
+    let setup = ProjectSetup::new();
+    let project_id = setup.create_project(15, &["cid0"]);
+    setup.contribute(&setup.user1, 15, project_id);
+    setup.submit_with_confidence(&setup.user2, "cid0", "cat", 85, project_id);
+
+    let annotations = setup
+        .client()
+        .get_annotations(&project_id, &Symbol::new(&setup.env, "cid0"));
+    assert_eq!(annotations.len(), 1);
+    assert_eq!(annotations.get(0).unwrap().confidence, 85);
+}
+
+#[test]
+#[should_panic(expected = "confidence out of range")]
+fn test_annotation_confidence_out_of_range() {
+    let setup = ProjectSetup::new();
+    let project_id = setup.create_project(15, &["cid0"]);
+    setup.contribute(&setup.user1, 15, project_id);
+
+    setup.submit_with_confidence(&setup.user2, "cid0", "cat", 150, project_id);
+}
+
+#[test]
+fn test_claim_all_success() {
+    let setup = ProjectSetup::new();
+    let config = ProjectConfig {
+        reward_per_annotation: 5,
+        ..default_config(&setup.env)
+    };
+    let first = setup.create_project_with_config(13, &["cid0", "cid1"], &config);
+    let second = setup.create_project_with_config(14, &["cid0", "cid1"], &config);
+    let running = setup.create_project_with_config(15, &["cid0", "cid1"], &config);
+    setup.contribute(&setup.user1, 13, first);
+    setup.contribute(&setup.user1, 14, second);
+    setup.contribute(&setup.user1, 5, running);
+    for project_id in [first, second] {
+        setup.submit(&setup.user2, "cid0", "cat", project_id);
+        setup.submit(&setup.user2, "cid1", "cat", project_id);
+    }
+    // the first project has settled its refunds, the second still owes its
+    // contributor 4 and is skipped, the running one is untouched
+    setup.submit(&setup.user1, "cid0", "cat", first);
+    assert_eq!(setup.client().claim_all_success(&setup.recipient), 0);
+    assert_eq!(
+        setup.client().get_project(&second, &false).current_amount,
+        4
+    );
+    assert_eq!(setup.token.balance(&setup.annotate_id), 9);
+}
+
+#[test]
+fn test_withdraw_after_expiry() {
+    let setup = ProjectSetup::new();
+    let project_id = setup.create_project(15, &["cid0"]);
+    setup.contribute(&setup.user1, 10, project_id);
+    advance_ledger(&setup.env, 11);
+
+    setup.client().withdraw(&setup.user1, &project_id);
+    assert_eq!(setup.token.balance(&setup.user1), 100);
+}
+
+#[test]
+#[should_panic(expected = "nothing to withdraw")]
+fn test_withdraw_without_deposit() {
+    let setup = ProjectSetup::new();
+    let project_id = setup.create_project(15, &["cid0"]);
+    setup.contribute(&setup.user1, 10, project_id);
+    advance_ledger(&setup.env, 11);
+
+    setup.client().withdraw(&setup.user2, &project_id);
+}
+
+#[test]
+fn test_currency_symbol() {
+    let setup = ProjectSetup::new();
+    let config = ProjectConfig {
+        currency_symbol: Symbol::new(&setup.env, "USDC"),
+        ..default_config(&setup.env)
+    };
+    let project_id = setup.create_project_with_config(15, &["cid0"], &config);
+    let unlabelled = setup.create_project(15, &["cid0"]);
+
+    assert_eq!(
+        setup.client().currency(&project_id),
+        Symbol::new(&setup.env, "USDC")
+    );
+    assert_eq!(
+        setup.client().currency(&unlabelled),
+        Symbol::new(&setup.env, "")
+    );
+}
+
+#[test]
+fn test_has_withdrawn() {
+    let setup = ProjectSetup::new();
+    let project_id = setup.create_project(15, &["cid0"]);
+    setup.contribute(&setup.user1, 10, project_id);
+    advance_ledger(&setup.env, 11);
+
+    assert!(!setup.client().has_withdrawn(&project_id, &setup.user1));
+    assert!(!setup.client().has_withdrawn(&project_id, &setup.user2));
+
+    setup.client().withdraw(&setup.user1, &project_id);
+
+    assert!(setup.client().has_withdrawn(&project_id, &setup.user1));
+    assert_eq!(setup.client().balance(&setup.user1, &project_id), 0);
+    // never contributing is still distinguishable from having withdrawn
+    assert!(!setup.client().has_withdrawn(&project_id, &setup.user2));
+}
+
+fn submit_classid(setup: &ProjectSetup, class_id: u32, project_id: u32) {
+    setup.client().submit_classid(
+        &setup.user2,
+        &Symbol::new(&setup.env, "cid0"),
+        &0,
+        &0,
+        &10,
+        &10,
+        &class_id,
+        &project_id,
+    );
+}
+
+#[test]
+fn test_submit_classid_in_range() {
+    let setup = ProjectSetup::new();
+    let config = ProjectConfig {
+        num_classes: 10,
+        ..default_config(&setup.env)
+    };
+    let project_id = setup.create_project_with_config(15, &["cid0"], &config);
+    setup.contribute(&setup.user1, 15, project_id);
+
+    submit_classid(&setup, 9, project_id);
+
+    let annotation =
+        setup
+            .client()
+            .get_annotation(&project_id, &Symbol::new(&setup.env, "cid0"), &0);
+    assert_eq!(annotation.class_id, Some(9));
+    assert_eq!(setup.token.balance(&setup.user2), 101);
+}
+
+#[test]
+#[should_panic(expected = "class id out of range")]
+fn test_submit_classid_out_of_range() {
+    let setup = ProjectSetup::new();
+    let config = ProjectConfig {
+        num_classes: 10,
+        ..default_config(&setup.env)
+    };
+    let project_id = setup.create_project_with_config(15, &["cid0"], &config);
+    setup.contribute(&setup.user1, 15, project_id);
+
+    submit_classid(&setup, 10, project_id);
+}
+
+#[test]
+fn test_started() {
+    let setup = ProjectSetup::new();
+    advance_ledger(&setup.env, 1_000);
+    let created_at = setup.env.ledger().timestamp();
+    let project_id = setup.create_project(15, &["cid0"]);
+    advance_ledger(&setup.env, 5);
+
+    assert_eq!(setup.client().started(&project_id), created_at);
+}
+
+#[test]
+fn test_withdraw_many_skips_running_projects() {
+    let setup = ProjectSetup::new();
+    setup.client().set_admin(&Address::generate(&setup.env));
+    let first = setup.create_project(15, &["cid0"]);
+    let second = setup.create_project(15, &["cid0"]);
+    let deleted = setup.create_project(15, &["cid0"]);
+    setup.contribute(&setup.user1, 4, first);
+    setup.contribute(&setup.user1, 6, second);
+    advance_ledger(&setup.env, 11);
+    setup.client().delete_project(&deleted);
+
+    let active = setup.create_project(15, &["cid0"]);
+    setup.contribute(&setup.user1, 5, active);
+
+    let refunded = setup.client().withdraw_many(
+        &setup.user1,
+        &vec![&setup.env, first, deleted, second, 99, active],
+    );
+
+    assert_eq!(refunded, 10);
+    assert_eq!(setup.token.balance(&setup.user1), 95);
+    assert_eq!(setup.client().balance(&setup.user1, &active), 5);
+    assert!(setup.client().has_withdrawn(&first, &setup.user1));
+    assert!(!setup.client().has_withdrawn(&active, &setup.user1));
+}
+
+fn edit_setup() -> (ProjectSetup<'static>, u32) {
+    let (setup, project_id) = ProjectSetup::with_config(
+        |e| ProjectConfig {
+            required_annotations: 2,
+            ..default_config(e)
+        },
+        &["cid0"],
+        15,
+    );
+    setup.contribute(&setup.user1, 15, project_id);
+    setup.submit(&setup.user2, "cid0", "cat", project_id);
+    (setup, project_id)
+}
+
+#[test]
+fn test_edit_annotation() {
+    let (setup, project_id) = edit_setup();
+    let cid = Symbol::new(&setup.env, "cid0");
+
+    setup.client().edit_annotation(
+        &project_id,
+        &cid,
+        &setup.user2,
+        &5,
+        &6,
+        &20,
+        &30,
+        &Symbol::new(&setup.env, "dog"),
+    );
+
+    let annotations = setup.client().get_annotations(&project_id, &cid);
+    assert_eq!(annotations.len(), 1);
+    let annotation = annotations.get(0).unwrap();
+    assert_eq!(
+        (
+            annotation.posx,
+            annotation.posy,
+            annotation.width,
+            annotation.height
+        ),
+        (5, 6, 20, 30)
+    );
+    assert_eq!(annotation.label, Symbol::new(&setup.env, "dog"));
+    // editing is free, only the original submission was rewarded
+    assert_eq!(setup.token.balance(&setup.user2), 101);
+}
+
+#[test]
+#[should_panic(expected = "data point finalized")]
+fn test_edit_annotation_after_finalization() {
+    let (setup, project_id) = edit_setup();
+    setup.submit(&setup.user1, "cid0", "cat", project_id);
+
+    setup.client().edit_annotation(
+        &project_id,
+        &Symbol::new(&setup.env, "cid0"),
+        &setup.user2,
+        &5,
+        &6,
+        &20,
+        &30,
+        &Symbol::new(&setup.env, "dog"),
+    );
+}
+
+#[test]
+#[should_panic(expected = "box too small")]
+fn test_edit_annotation_checks_box_size() {
+    let (setup, project_id) = ProjectSetup::with_config(
+        |e| ProjectConfig {
+            required_annotations: 2,
+            min_box_area: 100,
+            ..default_config(e)
+        },
+        &["cid0"],
+        15,
+    );
+    setup.contribute(&setup.user1, 15, project_id);
+    setup.submit(&setup.user2, "cid0", "cat", project_id);
+
+    setup.client().edit_annotation(
+        &project_id,
+        &Symbol::new(&setup.env, "cid0"),
+        &setup.user2,
+        &0,
+        &0,
+        &1,
+        &1,
+        &Symbol::new(&setup.env, "cat"),
+    );
+}
+
+#[test]
+#[should_panic(expected = "annotator banned")]
+fn test_edit_annotation_by_banned_annotator() {
+    let (setup, project_id) = edit_setup();
+    setup.client().set_admin(&Address::generate(&setup.env));
+    setup.client().ban_annotator(&setup.user2);
+
+    setup.client().edit_annotation(
+        &project_id,
+        &Symbol::new(&setup.env, "cid0"),
+        &setup.user2,
+        &5,
+        &6,
+        &20,
+        &30,
+        &Symbol::new(&setup.env, "dog"),
+    );
+}
+
+#[test]
+#[should_panic(expected = "no annotation to edit")]
+fn test_edit_annotation_without_one() {
+    let (setup, project_id) = edit_setup();
+
+    setup.client().edit_annotation(
+        &project_id,
+        &Symbol::new(&setup.env, "cid0"),
+        &setup.user1,
+        &5,
+        &6,
+        &20,
+        &30,
+        &Symbol::new(&setup.env, "dog"),
+    );
+}
+
+#[test]
+fn test_reward_budget_status() {
+    let setup = ProjectSetup::new();
+    let config = ProjectConfig {
+        reward_per_annotation: 2,
+        annotation_deadline: setup.env.ledger().timestamp() + 100,
+        ..default_config(&setup.env)
+    };
+    let project_id = setup.create_project_with_config(15, &["cid0", "cid1", "cid2"], &config);
+    setup.contribute(&setup.user1, 15, project_id);
+    // nothing is free while the deposits could still be refunded
+    assert_eq!(setup.client().reward_budget_status(&project_id), (0, 15));
+
+    setup.submit(&setup.user2, "cid0", "cat", project_id);
+    setup.submit(&setup.user2, "cid1", "cat", project_id);
+    setup.submit(&setup.user1, "cid2", "cat", project_id);
+    assert_eq!(setup.client().reward_budget_status(&project_id), (6, 9));
+
+    // at Success what the annotators left is owed back to the contributors
+    advance_ledger(&setup.env, 101);
+    assert_eq!(setup.client().state(&project_id), 2);
+    assert_eq!(setup.client().reward_budget_status(&project_id), (6, 0));
+}
+
+#[test]
+fn test_reward_budget_status_keeps_dust_after_claims() {
+    let (setup, project_id) = rounding_setup("floor");
+
+    // both contributors claimed, the unshared dust is free for the recipient
+    assert_eq!(setup.client().reward_budget_status(&project_id), (1, 1));
+}
+
+#[test]
+fn test_reward_budget_status_while_funding() {
+    let setup = ProjectSetup::new();
+    let project_id = setup.create_project(15, &["cid0"]);
+    setup.contribute(&setup.user1, 10, project_id);
+
+    assert_eq!(setup.client().reward_budget_status(&project_id), (0, 0));
+}
+
+#[test]
+fn test_data_point_count() {
+    let setup = ProjectSetup::new();
+    let project_id = setup.create_project(15, &["cid0", "cid1"]);
+    assert_eq!(setup.client().data_point_count(&project_id), 2);
+
+    // known cids are not counted twice
+    setup.client().add_data_points(
+        &project_id,
+        &vec![
+            &setup.env,
+            Symbol::new(&setup.env, "cid1"),
+            Symbol::new(&setup.env, "cid2"),
+        ],
+    );
+    assert_eq!(setup.client().data_point_count(&project_id), 3);
+    assert_eq!(setup.client().data_point_cids(&project_id).len(), 3);
+}
+
+#[test]
+#[should_panic(expected = "target below work cost")]
+fn test_add_data_points_above_target() {
+    let setup = ProjectSetup::new();
+    let project_id = setup.create_project(2, &["cid0", "cid1"]);
+    setup.client().add_data_points(
+        &project_id,
+        &vec![&setup.env, Symbol::new(&setup.env, "cid2")],
+    );
+}
+
+#[test]
+fn test_annotation_completes_before_annotation_deadline() {
+    let setup = ProjectSetup::new();
+    let config = ProjectConfig {
+        annotation_deadline: setup.env.ledger().timestamp() + 100,
+        ..default_config(&setup.env)
+    };
+    let project_id = setup.create_project_with_config(2, &["cid0", "cid1"], &config);
+    setup.contribute(&setup.user1, 2, project_id);
+    assert_eq!(setup.client().state(&project_id), 1);
+
+    setup.submit(&setup.user2, "cid0", "cat", project_id);
+    setup.submit(&setup.user2, "cid1", "cat", project_id);
+    assert_eq!(setup.client().state(&project_id), 2);
+    assert_eq!(setup.token.balance(&setup.user2), 102);
+}
+
+#[test]
+fn test_annotation_deadline_timeout() {
+    let setup = ProjectSetup::new();
+    let config = ProjectConfig {
+        annotation_deadline: setup.env.ledger().timestamp() + 100,
+        batch_rewards: true,
+        ..default_config(&setup.env)
+    };
+    let project_id = setup.create_project_with_config(15, &["cid0", "cid1"], &config);
+    setup.contribute(&setup.user1, 15, project_id);
+    setup.submit(&setup.user2, "cid0", "cat", project_id);
+    assert_eq!(setup.client().state(&project_id), 1);
+
+    advance_ledger(&setup.env, 101);
+    assert_eq!(setup.client().state(&project_id), 2);
+    // rewards held back by batch mode are released by the first call that settles the timeout
+    assert_eq!(setup.token.balance(&setup.user2), 100);
+    setup.submit(&setup.user1, "cid1", "cat", project_id);
+    assert_eq!(setup.token.balance(&setup.user2), 101);
+    assert_eq!(setup.token.balance(&setup.user1), 99);
+}
+
+#[test]
+#[should_panic(expected = "annotation deadline before funding deadline")]
+fn test_annotation_deadline_before_funding_deadline() {
+    let setup = ProjectSetup::new();
+    let config = ProjectConfig {
+        annotation_deadline: setup.env.ledger().timestamp() + 5,
+        ..default_config(&setup.env)
+    };
+    setup.create_project_with_config(15, &["cid0"], &config);
+}
+
+#[test]
+fn test_next_action_funding() {
+    let setup = ProjectSetup::new();
+    let project_id = setup.create_project(15, &["cid0"]);
+    setup.contribute(&setup.user1, 5, project_id);
+
+    assert_eq!(
+        setup.client().next_action(&project_id, &setup.user1),
+        Symbol::new(&setup.env, "contribute")
+    );
+}
+
+#[test]
+fn test_next_action_annotating() {
+    let setup = ProjectSetup::new();
+    let project_id = setup.create_project(15, &["cid0"]);
+    setup.contribute(&setup.user1, 15, project_id);
+
+    assert_eq!(
+        setup.client().next_action(&project_id, &setup.user2),
+        Symbol::new(&setup.env, "annotate")
+    );
+}
+
+#[test]
+fn test_next_action_expired() {
+    let setup = ProjectSetup::new();
+    let project_id = setup.create_project(15, &["cid0"]);
+    setup.contribute(&setup.user1, 5, project_id);
+    advance_ledger(&setup.env, 11);
+
+    assert_eq!(
+        setup.client().next_action(&project_id, &setup.user1),
+        Symbol::new(&setup.env, "withdraw")
+    );
+    assert_eq!(
+        setup.client().next_action(&project_id, &setup.user2),
+        Symbol::new(&setup.env, "none")
+    );
+
+    setup.client().withdraw(&setup.user1, &project_id);
+    assert_eq!(
+        setup.client().next_action(&project_id, &setup.user1),
+        Symbol::new(&setup.env, "none")
+    );
+}
+
+#[test]
+fn test_next_action_success() {
+    let (setup, project_id) = ProjectSetup::with_config(
+        |e| ProjectConfig {
+            annotation_deadline: e.ledger().timestamp() + 100,
+            ..default_config(e)
+        },
+        &["cid0"],
+        15,
+    );
+    setup.contribute(&setup.user1, 15, project_id);
+    advance_ledger(&setup.env, 101);
+    assert_eq!(
+        setup.client().next_action(&project_id, &setup.user1),
+        Symbol::new(&setup.env, "refund")
+    );
+    assert_eq!(
+        setup.client().next_action(&project_id, &setup.user2),
+        Symbol::new(&setup.env, "none")
+    );
+
+    setup.submit(&setup.user1, "cid0", "cat", project_id);
+    assert_eq!(
+        setup.client().next_action(&project_id, &setup.user1),
+        Symbol::new(&setup.env, "none")
+    );
+}
+
+#[test]
+#[should_panic(expected = "invalid recipient")]
+fn test_transfer_to_contract_rejected() {
+    let setup = ProjectSetup::new();
+    let project_id = setup.create_project(15, &["cid0"]);
+    setup.contribute(&setup.user1, 15, project_id);
+
+    // the reward would be paid from the contract straight back to itself
+    setup.submit(&setup.annotate_id, "cid0", "cat", project_id);
+}
+
+fn reassign_setup() -> (ProjectSetup<'static>, u32) {
+    let (setup, project_id) = ProjectSetup::with_config(
+        |e| ProjectConfig {
+            batch_rewards: true,
+            ..default_config(e)
+        },
+        &["cid0"],
+        15,
+    );
+    setup.contribute(&setup.user1, 15, project_id);
+    setup.submit(&setup.user2, "cid0", "cat", project_id);
+    (setup, project_id)
+}
+
+#[test]
+fn test_reassign_annotation_reward() {
+    let (setup, project_id) = reassign_setup();
+    let cid = Symbol::new(&setup.env, "cid0");
+    let owner = Address::generate(&setup.env);
+
+    setup
+        .client()
+        .reassign_annotation_reward(&project_id, &cid, &0, &owner);
+    let auths = setup.env.auths();
+    assert_eq!(auths.len(), 1);
+    assert_eq!(auths[0].0, setup.user2);
+    assert_eq!(
+        setup
+            .client()
+            .get_annotation(&project_id, &cid, &0)
+            .annotator,
+        owner
+    );
+
+    setup.client().finalize(&project_id);
+    assert_eq!(setup.token.balance(&owner), 1);
+    assert_eq!(setup.token.balance(&setup.user2), 100);
+}
+
+#[test]
+fn test_reassign_annotation_reward_moves_credited_amount() {
+    let (setup, project_id) = ProjectSetup::with_config(
+        |e| {
+            let mut label_multiplier = Map::new(e);
+            label_multiplier.set(Symbol::new(e, "hard"), 300);
+            ProjectConfig {
+                batch_rewards: true,
+                label_multiplier,
+                ..default_config(e)
+            }
+        },
+        &["cid0"],
+        15,
+    );
+    setup.contribute(&setup.user1, 15, project_id);
+    setup.submit(&setup.user2, "cid0", "hard", project_id);
+    let owner = Address::generate(&setup.env);
+
+    setup.client().reassign_annotation_reward(
+        &project_id,
+        &Symbol::new(&setup.env, "cid0"),
+        &0,
+        &owner,
+    );
+
+    // the tripled reward moves, not the plain per-annotation one
+    setup.client().finalize(&project_id);
+    assert_eq!(setup.token.balance(&owner), 3);
+    assert_eq!(setup.token.balance(&setup.user2), 100);
+}
+
+#[test]
+fn test_reassign_annotation_reward_requires_annotator() {
+    let (setup, project_id) = reassign_setup();
+    let cid = Symbol::new(&setup.env, "cid0");
+
+    setup.env.set_auths(&[]);
+    assert!(setup
+        .client()
+        .try_reassign_annotation_reward(&project_id, &cid, &0, &setup.user1)
+        .is_err());
+}
+
+#[test]
+#[should_panic(expected = "reward already paid")]
+fn test_reassign_annotation_reward_after_payout() {
+    let setup = ProjectSetup::new();
+    let project_id = setup.create_project(15, &["cid0"]);
+    setup.contribute(&setup.user1, 15, project_id);
+    setup.submit(&setup.user2, "cid0", "cat", project_id);
+
+    setup.client().reassign_annotation_reward(
+        &project_id,
+        &Symbol::new(&setup.env, "cid0"),
+        &0,
+        &setup.user1,
+    );
+}
+
+#[test]
+fn test_project_ids() {
+    let setup = ProjectSetup::new();
+    assert!(setup.client().project_ids().is_empty());
+
+    let first = setup.create_project(15, &["cid0"]);
+    let second = setup.create_project(15, &["cid0"]);
+    let third = setup.create_project(15, &["cid0"]);
+
+    assert_eq!(
+        setup.client().project_ids(),
+        vec![&setup.env, first, second, third]
+    );
+}
+
+#[test]
+fn test_min_box_area() {
+    let setup = ProjectSetup::new();
+    let config = ProjectConfig {
+        min_box_area: 100,
+        ..default_config(&setup.env)
+    };
+    let project_id = setup.create_project_with_config(15, &["cid0"], &config);
+    setup.contribute(&setup.user1, 15, project_id);
+
+    // the helper draws a 10x10 box, exactly at the minimum
+    setup.submit(&setup.user2, "cid0", "cat", project_id);
+    assert_eq!(setup.token.balance(&setup.user2), 101);
+}
+
+#[test]
+#[should_panic(expected = "box too small")]
+fn test_min_box_area_rejects_small_box() {
+    let setup = ProjectSetup::new();
+    let config = ProjectConfig {
+        min_box_area: 100,
+        ..default_config(&setup.env)
+    };
+    let project_id = setup.create_project_with_config(15, &["cid0"], &config);
+    setup.contribute(&setup.user1, 15, project_id);
+
+    setup.client().submit(
+        &setup.user2,
+        &Symbol::new(&setup.env, "cid0"),
+        &0,
+        &0,
+        &9,
+        &11,
+        &Symbol::new(&setup.env, "cat"),
+        &100,
+        &project_id,
+    );
+}
+
+#[test]
+fn test_reopen_expired_project() {
+    let setup = ProjectSetup::new();
+    let admin = Address::generate(&setup.env);
+    setup.client().set_admin(&admin);
+    let project_id = setup.create_project(15, &["cid0"]);
+    setup.contribute(&setup.user1, 5, project_id);
+    advance_ledger(&setup.env, 11);
+    assert_eq!(setup.client().state(&project_id), 3);
+
+    let new_deadline = setup.env.ledger().timestamp() + 10;
+    setup.client().reopen(&project_id, &new_deadline);
+    let auths = setup.env.auths();
+    assert_eq!(auths.len(), 1);
+    assert_eq!(auths[0].0, admin);
+    assert_eq!(setup.client().state(&project_id), 0);
+    assert_eq!(setup.client().deadline(&project_id), new_deadline);
+
+    setup.contribute(&setup.user2, 10, project_id);
+    assert_eq!(setup.client().state(&project_id), 1);
+}
+
+#[test]
+#[should_panic(expected = "withdrawals already made")]
+fn test_reopen_after_withdrawal() {
+    let setup = ProjectSetup::new();
+    setup.client().set_admin(&Address::generate(&setup.env));
+    let project_id = setup.create_project(15, &["cid0"]);
+    setup.contribute(&setup.user1, 5, project_id);
+    setup.contribute(&setup.user2, 5, project_id);
+    advance_ledger(&setup.env, 11);
+    setup.client().withdraw(&setup.user1, &project_id);
+
+    let new_deadline = setup.env.ledger().timestamp() + 10;
+    setup.client().reopen(&project_id, &new_deadline);
+}
+
+#[test]
+fn test_global_stats() {
+    let setup = ProjectSetup::new();
+    assert_eq!(setup.client().global_stats(), (0, 0, 0));
+
+    let first = setup.create_project(15, &["cid0", "cid1"]);
+    let second = setup.create_project(20, &["cid0"]);
+    setup.contribute(&setup.user1, 15, first);
+    setup.contribute(&setup.user2, 7, second);
+    setup.submit(&setup.user2, "cid0", "cat", first);
+    setup.submit(&setup.user2, "cid1", "dog", first);
+
+    assert_eq!(setup.client().global_stats(), (2, 22, 2));
+}
+
+#[test]
+fn test_gateway() {
+    let setup = ProjectSetup::new();
+    let config = ProjectConfig {
+        gateway: String::from_str(&setup.env, "https://ipfs.io/ipfs/"),
+        ..default_config(&setup.env)
+    };
+    let project_id = setup.create_project_with_config(15, &["cid0"], &config);
+    let plain = setup.create_project(15, &["cid0"]);
+
+    assert_eq!(
+        setup.client().gateway(&project_id),
+        String::from_str(&setup.env, "https://ipfs.io/ipfs/")
+    );
+    assert_eq!(
+        setup.client().gateway(&plain),
+        String::from_str(&setup.env, "")
+    );
+}
+
+fn consensus_setup(required_annotations: u32) -> (ProjectSetup<'static>, u32) {
+    let (setup, project_id) = ProjectSetup::with_config(
+        |e| ProjectConfig {
+            required_annotations,
+            ..default_config(e)
+        },
+        &["cid0"],
+        15,
+    );
+    setup.contribute(&setup.user1, 15, project_id);
+    (setup, project_id)
+}
+
+#[test]
+fn test_consensus_label_majority() {
+    let (setup, project_id) = consensus_setup(3);
+    setup.submit(&setup.user1, "cid0", "cat", project_id);
+    setup.submit(&setup.user2, "cid0", "dog", project_id);
+    setup.submit(&setup.recipient, "cid0", "cat", project_id);
+
+    assert_eq!(
+        setup
+            .client()
+            .consensus_label(&project_id, &Symbol::new(&setup.env, "cid0")),
+        Symbol::new(&setup.env, "cat")
+    );
+}
+
+#[test]
+fn test_consensus_label_tie_picks_first() {
+    let (setup, project_id) = consensus_setup(2);
+    setup.submit(&setup.user1, "cid0", "dog", project_id);
+    setup.submit(&setup.user2, "cid0", "cat", project_id);
+
+    assert_eq!(
+        setup
+            .client()
+            .consensus_label(&project_id, &Symbol::new(&setup.env, "cid0")),
+        Symbol::new(&setup.env, "dog")
+    );
+}
+
+#[test]
+#[should_panic(expected = "data point not finalized")]
+fn test_consensus_label_before_finalization() {
+    let (setup, project_id) = consensus_setup(2);
+    setup.submit(&setup.user1, "cid0", "dog", project_id);
+
+    setup
+        .client()
+        .consensus_label(&project_id, &Symbol::new(&setup.env, "cid0"));
+}
+
+#[test]
+fn test_contributions_since() {
+    let setup = ProjectSetup::new();
+    let start = setup.env.ledger().timestamp();
+    let project_id = setup.create_project(15, &["cid0"]);
+    setup.contribute(&setup.user1, 3, project_id);
+    advance_ledger(&setup.env, 3);
+    setup.contribute(&setup.user2, 4, project_id);
+    advance_ledger(&setup.env, 3);
+    setup.contribute(&setup.user1, 2, project_id);
+
+    assert_eq!(setup.client().contributions_since(&project_id, &start), 6);
+    assert_eq!(
+        setup
+            .client()
+            .contributions_since(&project_id, &(start + 3)),
+        2
+    );
+    assert_eq!(
+        setup
+            .client()
+            .contributions_since(&project_id, &(start + 6)),
+        0
+    );
+}
+
+#[test]
+fn test_submit_pays_after_recording() {
+    let setup = ProjectSetup::new();
+    let e = &setup.env;
+    let project_id = setup.create_project(15, &["cid0"]);
+    setup.contribute(&setup.user1, 15, project_id);
+    setup.submit(&setup.user2, "cid0", "cat", project_id);
+
+    // a panic rolls the whole call back anyway, so what matters is that the
+    // books are written before the reward leaves the contract
+    let mut order = Vec::<Symbol>::new(e);
+    for event in e.events().all().iter() {
+        let name: Symbol = event.1.get(0).unwrap().into_val(e);
+        if event.0 == setup.annotate_id || name == Symbol::new(e, "transfer") {
+            order.push_back(name);
+        }
+    }
+    // the tail is the submit, after the contribution's events
+    assert_eq!(
+        order.slice(order.len() - 2..),
+        vec![
+            e,
+            Symbol::new(e, "earnings_changed"),
+            Symbol::new(e, "transfer")
+        ]
+    );
+}
+
+#[test]
+fn test_sweep_waits_for_refunds() {
+    let setup = ProjectSetup::new();
+    let config = ProjectConfig {
+        annotation_deadline: setup.env.ledger().timestamp() + 100,
+        ..default_config(&setup.env)
+    };
+    let project_id = setup.create_project_with_config(15, &["cid0"], &config);
+    setup.contribute(&setup.user1, 10, project_id);
+    setup.contribute(&setup.user2, 5, project_id);
+    setup.submit(&setup.recipient, "cid0", "cat", project_id);
+    advance_ledger(&setup.env, 101);
+
+    assert!(setup.client().try_sweep_remainder(&project_id).is_err());
+    setup.submit(&setup.user1, "cid0", "cat", project_id);
+    assert!(setup.client().try_sweep_remainder(&project_id).is_err());
+    setup.submit(&setup.user2, "cid0", "cat", project_id);
+
+    // 14 left after the reward, split 10:5 between the two contributors and
+    // rounded down, the dust goes to the recipient
+    assert_eq!(setup.token.balance(&setup.user1), 99);
+    assert_eq!(setup.token.balance(&setup.user2), 99);
+    assert_eq!(setup.client().sweep_remainder(&project_id), 1);
+}
+
+#[test]
+#[should_panic(expected = "refunds outstanding")]
+fn test_sweep_rejected_with_refunds_outstanding() {
+    let setup = ProjectSetup::new();
+    let config = ProjectConfig {
+        annotation_deadline: setup.env.ledger().timestamp() + 100,
+        ..default_config(&setup.env)
+    };
+    let project_id = setup.create_project_with_config(15, &["cid0"], &config);
+    setup.contribute(&setup.user1, 15, project_id);
+    advance_ledger(&setup.env, 101);
+
+    setup.client().sweep_remainder(&project_id);
+}
+
+#[test]
+fn test_is_recipient() {
+    let setup = ProjectSetup::new();
+    let project_id = setup.create_project(15, &["cid0"]);
+
+    assert!(setup.client().is_recipient(&project_id, &setup.recipient));
+    assert!(!setup.client().is_recipient(&project_id, &setup.user1));
+}
+
+#[test]
+fn test_label_display() {
+    let setup = ProjectSetup::new();
+    let e = &setup.env;
+    let mut label_display = Map::new(e);
+    label_display.set(Symbol::new(e, "c0"), Symbol::new(e, "cat"));
+    let config = ProjectConfig {
+        label_display,
+        ..default_config(e)
+    };
+    let project_id = setup.create_project_with_config(15, &["cid0"], &config);
+
+    assert_eq!(
+        setup
+            .client()
+            .label_display(&project_id, &Symbol::new(e, "c0")),
+        Symbol::new(e, "cat")
+    );
+    assert_eq!(
+        setup
+            .client()
+            .label_display(&project_id, &Symbol::new(e, "c1")),
+        Symbol::new(e, "c1")
+    );
+}
+
+fn cooldown_setup() -> (ProjectSetup<'static>, u32) {
+    let (setup, project_id) = ProjectSetup::with_config(
+        |e| ProjectConfig {
+            submit_cooldown: 5,
+            ..default_config(e)
+        },
+        &["cid0", "cid1"],
+        15,
+    );
+    setup.contribute(&setup.user1, 15, project_id);
+    setup.submit(&setup.user2, "cid0", "cat", project_id);
+    (setup, project_id)
+}
+
+#[test]
+#[should_panic(expected = "cooldown")]
+fn test_submit_cooldown_rejects_back_to_back() {
+    let (setup, project_id) = cooldown_setup();
+    setup.submit(&setup.user2, "cid1", "cat", project_id);
+}
+
+#[test]
+fn test_submit_cooldown_elapsed() {
+    let (setup, project_id) = cooldown_setup();
+    // other annotators are not held up
+    setup.submit(&setup.user1, "cid1", "cat", project_id);
+
+    advance_ledger(&setup.env, 5);
+    setup.submit(&setup.user2, "cid1", "cat", project_id);
+    assert_eq!(setup.token.balance(&setup.user2), 102);
+}
+
+#[test]
+#[should_panic(expected = "cooldown")]
+fn test_submit_cooldown_does_not_overflow() {
+    let (setup, project_id) = ProjectSetup::with_config(
+        |e| ProjectConfig {
+            submit_cooldown: u64::MAX,
+            ..default_config(e)
+        },
+        &["cid0", "cid1"],
+        15,
+    );
+    setup.contribute(&setup.user1, 15, project_id);
+    setup.submit(&setup.user2, "cid0", "cat", project_id);
+
+    setup.submit(&setup.user2, "cid1", "cat", project_id);
+}
+
+#[test]
+fn test_total_earnings_paid() {
+    let setup = ProjectSetup::new();
+    let config = ProjectConfig {
+        reward_per_annotation: 3,
+        ..default_config(&setup.env)
+    };
+    let project_id = setup.create_project_with_config(15, &["cid0", "cid1", "cid2"], &config);
+    setup.contribute(&setup.user1, 15, project_id);
+    assert_eq!(setup.client().total_earnings_paid(&project_id), 0);
+
+    setup.submit(&setup.user2, "cid0", "cat", project_id);
+    setup.submit(&setup.user2, "cid1", "cat", project_id);
+    setup.submit(&setup.user1, "cid2", "cat", project_id);
+
+    assert_eq!(setup.client().total_earnings_paid(&project_id), 3 * 3);
+}
+
+#[test]
+fn test_get_projects_skips_missing_entries() {
+    let setup = ProjectSetup::new();
+    let first = setup.create_project(15, &["cid0"]);
+    let removed = setup.create_project(15, &["cid0"]);
+    let last = setup.create_project(15, &["cid0"]);
+    let e = &setup.env;
+    e.as_contract(&setup.annotate_id, || {
+        e.storage().instance().remove(&DataKey::Project(removed));
+    });
+
+    let projects = setup.client().get_projects();
+    assert_eq!(projects.len(), 2);
+    assert_eq!(projects.get(0).unwrap().id, first);
+    assert_eq!(projects.get(1).unwrap().id, last);
+}
+
+#[test]
+fn test_delete_project() {
+    let setup = ProjectSetup::new();
+    setup.client().set_admin(&Address::generate(&setup.env));
+    let project_id = setup.create_project(15, &["cid0"]);
+    let kept = setup.create_project(15, &["cid0"]);
+    setup.contribute(&setup.user1, 5, project_id);
+    advance_ledger(&setup.env, 11);
+    setup.client().withdraw(&setup.user1, &project_id);
+
+    setup.client().delete_project(&project_id);
+    assert_eq!(setup.client().project_ids(), vec![&setup.env, kept]);
+    assert_eq!(setup.client().get_projects().len(), 1);
+}
+
+#[test]
+#[should_panic(expected = "deposits remain")]
+fn test_delete_project_with_deposits() {
+    let setup = ProjectSetup::new();
+    setup.client().set_admin(&Address::generate(&setup.env));
+    let project_id = setup.create_project(15, &["cid0"]);
+    setup.contribute(&setup.user1, 5, project_id);
+    advance_ledger(&setup.env, 11);
+
+    setup.client().delete_project(&project_id);
+}
+
+#[test]
+#[should_panic(expected = "funds remain")]
+fn test_delete_project_with_unswept_funds() {
+    // every contributor has claimed, the rounding dust is left for the recipient
+    let (setup, project_id) = rounding_setup("floor");
+    setup.client().set_admin(&Address::generate(&setup.env));
+
+    setup.client().delete_project(&project_id);
+}
+
+#[test]
+#[should_panic(expected = "stakes remain")]
+fn test_delete_project_with_stakes() {
+    let (setup, project_id) = ProjectSetup::with_config(
+        |e| ProjectConfig {
+            annotator_stake: 5,
+            ..default_config(e)
+        },
+        &["cid0"],
+        15,
+    );
+    setup.client().set_admin(&Address::generate(&setup.env));
+    advance_ledger(&setup.env, 11);
+    setup.client().stake(&setup.user2, &project_id);
+
+    setup.client().delete_project(&project_id);
+}
+
+fn soft_cap_setup() -> (ProjectSetup<'static>, u32) {
+    let (setup, project_id) = ProjectSetup::with_config(
+        |e| ProjectConfig {
+            soft_cap: 8,
+            soft_cap_deadline: e.ledger().timestamp() + 5,
+            ..default_config(e)
+        },
+        &["cid0"],
+        15,
+    );
+    (setup, project_id)
+}
+
+#[test]
+fn test_soft_cap_met() {
+    let (setup, project_id) = soft_cap_setup();
+    setup.contribute(&setup.user1, 8, project_id);
+    advance_ledger(&setup.env, 6);
+
+    assert_eq!(setup.client().check_soft_cap(&project_id), 0);
+    setup.contribute(&setup.user2, 7, project_id);
+    assert_eq!(setup.client().state(&project_id), 1);
+}
+
+#[test]
+fn test_soft_cap_missed() {
+    let (setup, project_id) = soft_cap_setup();
+    setup.contribute(&setup.user1, 7, project_id);
+    advance_ledger(&setup.env, 6);
+
+    // expired before the funding deadline, so the deposit can come back
+    assert_eq!(setup.client().check_soft_cap(&project_id), 3);
+    setup.client().withdraw(&setup.user1, &project_id);
+    assert_eq!(setup.token.balance(&setup.user1), 100);
+}
+
+#[test]
+fn test_reopen_after_soft_cap_missed() {
+    let (setup, project_id) = soft_cap_setup();
+    setup.client().set_admin(&Address::generate(&setup.env));
+    setup.contribute(&setup.user1, 7, project_id);
+    advance_ledger(&setup.env, 6);
+    assert_eq!(setup.client().state(&project_id), 3);
+
+    let new_deadline = setup.env.ledger().timestamp() + 10;
+    setup.client().reopen(&project_id, &new_deadline);
+    assert_eq!(setup.client().state(&project_id), 0);
+
+    // the soft cap now has to be met by the new deadline
+    setup.contribute(&setup.user2, 1, project_id);
+    advance_ledger(&setup.env, 10);
+    assert_eq!(setup.client().check_soft_cap(&project_id), 0);
+}
+
+#[test]
+fn test_fees_accrue_and_collect() {
+    let setup = ProjectSetup::new();
+    setup.client().set_admin(&Address::generate(&setup.env));
+    let collector = Address::generate(&setup.env);
+    setup.client().set_fee_collector(&collector, &1_000);
+    let project_id = setup.create_project(30, &["cid0"]);
+
+    setup.contribute(&setup.user1, 10, project_id);
+    setup.contribute(&setup.user2, 10, project_id);
+    setup.contribute(&setup.user1, 5, project_id);
+
+    // 10% of each deposit, rounded down
+    assert_eq!(setup.client().fees_accrued(&setup.token.address), 2);
+    assert_eq!(setup.client().balance(&setup.user1, &project_id), 9 + 5);
+    assert_eq!(setup.token.balance(&collector), 0);
+
+    setup.client().collect_fees();
+    assert_eq!(setup.env.auths()[0].0, collector);
+    assert_eq!(setup.token.balance(&collector), 2);
+    assert_eq!(setup.client().fees_accrued(&setup.token.address), 0);
+    assert_eq!(setup.token.balance(&setup.annotate_id), 23);
+}
+
+#[test]
+fn test_simulate_contribute_with_fee() {
+    let setup = ProjectSetup::new();
+    setup.client().set_admin(&Address::generate(&setup.env));
+    setup
+        .client()
+        .set_fee_collector(&Address::generate(&setup.env), &1_000);
+    let project_id = setup.create_project(15, &["cid0"]);
+
+    // 10% of 10 goes to the fee collector, so it doesn't reach the target
+    assert_eq!(
+        setup.client().simulate_contribute(&project_id, &10),
+        (State::Funding as u32, 9)
+    );
+    setup.contribute(&setup.user1, 10, project_id);
+    assert_eq!(
+        setup
+            .client()
+            .get_project(&project_id, &false)
+            .current_amount,
+        9
+    );
+}
+
+fn signed_setup() -> (ProjectSetup<'static>, Keypair, u32) {
+    let keypair = Keypair::generate(&mut rand::thread_rng());
+    let (setup, project_id) = ProjectSetup::with_config(
+        |e| ProjectConfig {
+            trusted_signer: BytesN::from_array(e, &keypair.public.to_bytes()),
+            ..default_config(e)
+        },
+        &["cid0"],
+        15,
+    );
+    setup.contribute(&setup.user1, 15, project_id);
+    (setup, keypair, project_id)
+}
+
+fn sign_annotation(setup: &ProjectSetup, keypair: &Keypair, project_id: u32) -> BytesN<64> {
+    let e = &setup.env;
+    let payload = (
+        setup.user2.clone(),
+        project_id,
+        Symbol::new(e, "cid0"),
+        0u32,
+        0u32,
+        10u32,
+        10u32,
+        Symbol::new(e, "cat"),
+    )
+        .to_xdr(e);
+    let message: std::vec::Vec<u8> = payload.iter().collect();
+    BytesN::from_array(e, &keypair.sign(&message).to_bytes())
+}
+
+fn submit_signed(
+    setup: &ProjectSetup,
+    sig: &BytesN<64>,
+    pubkey: &BytesN<32>,
+    project_id: u32,
+) -> bool {
+    let e = &setup.env;
+    setup
+        .client()
+        .try_submit_signed(
+            &setup.user2,
+            &Symbol::new(e, "cid0"),
+            &0,
+            &0,
+            &10,
+            &10,
+            &Symbol::new(e, "cat"),
+            sig,
+            pubkey,
+            &project_id,
+        )
+        .is_ok()
+}
+
+#[test]
+fn test_submit_signed() {
+    let (setup, keypair, project_id) = signed_setup();
+    let sig = sign_annotation(&setup, &keypair, project_id);
+    let pubkey = BytesN::from_array(&setup.env, &keypair.public.to_bytes());
+
+    assert!(submit_signed(&setup, &sig, &pubkey, project_id));
+    assert_eq!(setup.token.balance(&setup.user2), 101);
+}
+
+#[test]
+fn test_submit_signed_invalid_signature() {
+    let (setup, keypair, project_id) = signed_setup();
+    let pubkey = BytesN::from_array(&setup.env, &keypair.public.to_bytes());
+    // signed for a different project, so it doesn't cover this payload
+    let sig = sign_annotation(&setup, &keypair, project_id + 1);
+
+    assert!(!submit_signed(&setup, &sig, &pubkey, project_id));
+    assert_eq!(setup.token.balance(&setup.user2), 100);
+}
+
+#[test]
+fn test_reward_halving() {
+    let setup = ProjectSetup::new();
+    let config = ProjectConfig {
+        reward_per_annotation: 4,
+        reward_halving_interval: 10,
+        ..default_config(&setup.env)
+    };
+    let project_id = setup.create_project_with_config(15, &["cid0", "cid1", "cid2"], &config);
+    setup.contribute(&setup.user1, 15, project_id);
+    assert_eq!(setup.client().current_reward(&project_id), 4);
+
+    advance_ledger(&setup.env, 9);
+    assert_eq!(setup.client().current_reward(&project_id), 4);
+    setup.submit(&setup.user2, "cid0", "cat", project_id);
+    assert_eq!(setup.token.balance(&setup.user2), 104);
+
+    advance_ledger(&setup.env, 1);
+    assert_eq!(setup.client().current_reward(&project_id), 2);
+    setup.submit(&setup.user2, "cid1", "cat", project_id);
+    assert_eq!(setup.token.balance(&setup.user2), 106);
+
+    // never halves below a single token
+    advance_ledger(&setup.env, 100);
+    assert_eq!(setup.client().current_reward(&project_id), 1);
+}
+
+#[test]
+fn test_reward_halving_after_reduce_target() {
+    let (setup, project_id) = ProjectSetup::with_config(
+        |e| ProjectConfig {
+            reward_per_annotation: 4,
+            reward_halving_interval: 10,
+            ..default_config(e)
+        },
+        &["cid0", "cid1"],
+        15,
+    );
+    setup.contribute(&setup.user1, 10, project_id);
+    advance_ledger(&setup.env, 5);
+    setup.client().reduce_target(&project_id, &10);
+    assert_eq!(
+        setup
+            .client()
+            .get_project(&project_id, &false)
+            .annotating_since,
+        Some(setup.env.ledger().timestamp())
+    );
+
+    // the schedule starts at the reduction, not at the project's creation
+    advance_ledger(&setup.env, 9);
+    assert_eq!(setup.client().current_reward(&project_id), 4);
+    advance_ledger(&setup.env, 1);
+    assert_eq!(setup.client().current_reward(&project_id), 2);
+}
+
+fn multiplier_config(e: &Env, multiplier: u32) -> ProjectConfig {
+    let mut label_multiplier = Map::new(e);
+    label_multiplier.set(Symbol::new(e, "hard"), multiplier);
+    ProjectConfig {
+        reward_per_annotation: 2,
+        label_multiplier,
+        ..default_config(e)
+    }
+}
+
+#[test]
+fn test_label_multiplier() {
+    let setup = ProjectSetup::new();
+    let config = multiplier_config(&setup.env, 200);
+    let project_id = setup.create_project_with_config(15, &["cid0", "cid1"], &config);
+    setup.contribute(&setup.user1, 15, project_id);
+
+    setup.submit(&setup.user2, "cid0", "hard", project_id);
+    assert_eq!(setup.token.balance(&setup.user2), 104);
+    // unmapped labels are weighted at 100%
+    setup.submit(&setup.user2, "cid1", "cat", project_id);
+    assert_eq!(setup.token.balance(&setup.user2), 106);
+}
+
+#[test]
+#[should_panic(expected = "multiplier must be positive")]
+fn test_label_multiplier_zero() {
+    let setup = ProjectSetup::new();
+    let config = multiplier_config(&setup.env, 0);
+    setup.create_project_with_config(15, &["cid0"], &config);
+}
+
+#[test]
+fn test_is_target_reached() {
+    let setup = ProjectSetup::new();
+    let exact = setup.create_project(15, &["cid0"]);
+    let over = setup.create_project(15, &["cid0"]);
+    setup.contribute(&setup.user1, 14, exact);
+    setup.contribute(&setup.user2, 20, over);
+
+    assert!(!setup.client().is_target_reached(&exact));
+    setup.contribute(&setup.user2, 1, exact);
+    assert!(setup.client().is_target_reached(&exact));
+    assert!(setup.client().is_target_reached(&over));
+}
+
+fn aggregate_setup() -> (ProjectSetup<'static>, u32) {
+    let (setup, project_id) = ProjectSetup::with_config(
+        |e| ProjectConfig {
+            track_per_contributor: false,
+            ..default_config(e)
+        },
+        &["cid0"],
+        15,
+    );
+    setup.contribute(&setup.user1, 5, project_id);
+    setup.contribute(&setup.user2, 4, project_id);
+    (setup, project_id)
+}
+
+#[test]
+fn test_aggregate_only_contributions() {
+    let (setup, project_id) = aggregate_setup();
+
+    assert_eq!(setup.client().remaining_to_target(&project_id), 6);
+    assert_eq!(setup.client().contribution_count(&project_id), 2);
+    assert_eq!(setup.client().balance(&setup.user1, &project_id), 0);
+    assert_eq!(setup.client().balance(&setup.user2, &project_id), 0);
+    assert!(setup
+        .client()
+        .get_project(&project_id, &false)
+        .contributors_contribution_map
+        .is_empty());
+}
+
+#[test]
+#[should_panic(expected = "nothing to withdraw")]
+fn test_aggregate_only_has_no_refunds() {
+    let (setup, project_id) = aggregate_setup();
+    advance_ledger(&setup.env, 11);
+
+    setup.client().withdraw(&setup.user1, &project_id);
+}
+
+#[test]
+fn test_aggregate_only_expiry_sweeps_to_recipient() {
+    let (setup, project_id) = aggregate_setup();
+    setup.client().set_admin(&Address::generate(&setup.env));
+    advance_ledger(&setup.env, 11);
+
+    assert_eq!(setup.client().sweep_remainder(&project_id), 9);
+    assert_eq!(setup.token.balance(&setup.recipient), 9);
+    setup.client().delete_project(&project_id);
+}
+
+#[test]
+#[should_panic(expected = "project not successful")]
+fn test_sweep_expired_tracked_project() {
+    let setup = ProjectSetup::new();
+    let project_id = setup.create_project(15, &["cid0"]);
+    setup.contribute(&setup.user1, 5, project_id);
+    advance_ledger(&setup.env, 11);
+
+    setup.client().sweep_remainder(&project_id);
+}
+
+#[test]
+#[should_panic(expected = "funding closed: annotating")]
+fn test_contribute_while_annotating() {
+    let setup = ProjectSetup::new();
+    let project_id = setup.create_project(15, &["cid0"]);
+    setup.contribute(&setup.user1, 15, project_id);
+
+    setup.contribute(&setup.user2, 1, project_id);
+}
+
+#[test]
+#[should_panic(expected = "funding closed: completed")]
+fn test_contribute_after_completion() {
+    let setup = ProjectSetup::new();
+    let project_id = setup.create_project(1, &["cid0"]);
+    setup.contribute(&setup.user1, 1, project_id);
+    setup.submit(&setup.user2, "cid0", "cat", project_id);
+
+    setup.contribute(&setup.user2, 1, project_id);
+}
+
+#[test]
+#[should_panic(expected = "funding closed: expired")]
+fn test_contribute_after_expiry() {
+    let setup = ProjectSetup::new();
+    let project_id = setup.create_project(15, &["cid0"]);
+    advance_ledger(&setup.env, 11);
+
+    setup.contribute(&setup.user1, 1, project_id);
+}
+
+#[test]
+fn test_snapshot() {
+    let setup = ProjectSetup::new();
+    let e = &setup.env;
+    let project_id = setup.create_project(15, &["cid0", "cid1", "cid2"]);
+    setup.contribute(&setup.user1, 15, project_id);
+    setup.submit(&setup.user2, "cid1", "cat", project_id);
+
+    let stored = e.as_contract(&setup.annotate_id, || {
+        e.storage()
+            .instance()
+            .get::<_, Project>(&DataKey::Project(project_id))
+            .unwrap()
+    });
+    let snapshot = setup.client().snapshot(&project_id);
+    assert_eq!(snapshot.clone().to_xdr(e), stored.to_xdr(e));
+
+    let page = setup.client().snapshot_data_points(&project_id, &1, &5);
+    assert_eq!(page.len(), 2);
+    assert_eq!(page.get(0).unwrap().cid, Symbol::new(e, "cid1"));
+    assert_eq!(page.get(0).unwrap().annotations.len(), 1);
+    assert_eq!(page.get(1).unwrap().cid, Symbol::new(e, "cid2"));
+}
+
+fn last_event_payload(setup: &ProjectSetup, topics: Vec<Val>) -> Val {
+    let mut payload = None;
+    for event in setup.env.events().all().iter() {
+        if event.0 == setup.annotate_id && event.1 == topics {
+            payload = Some(event.2);
+        }
+    }
+    payload.unwrap()
+}
+
+#[test]
+fn test_target_reduced_event() {
+    let setup = ProjectSetup::new();
+    let e = &setup.env;
+    let project_id = setup.create_project(15, &["cid0"]);
+    setup.client().reduce_target(&project_id, &10);
+
+    assert_eq!(setup.client().target(&project_id), 10);
+    let topics = (Symbol::new(e, "target_reduced"), project_id).into_val(e);
+    let payload: Vec<i128> = last_event_payload(&setup, topics).into_val(e);
+    assert_eq!(payload, vec![e, 15, 10]);
+}
+
+#[test]
+fn test_deadline_extended_event() {
+    let setup = ProjectSetup::new();
+    let e = &setup.env;
+    let project_id = setup.create_project(15, &["cid0"]);
+    let old_deadline = setup.client().deadline(&project_id);
+    setup
+        .client()
+        .extend_deadline(&project_id, &(old_deadline + 20));
+
+    assert_eq!(setup.client().deadline(&project_id), old_deadline + 20);
+    let topics = (Symbol::new(e, "deadline_extended"), project_id).into_val(e);
+    let payload: Vec<u64> = last_event_payload(&setup, topics).into_val(e);
+    assert_eq!(payload, vec![e, old_deadline, old_deadline + 20]);
+}
+
+#[test]
+fn test_stored_state_lags_behind() {
+    let setup = ProjectSetup::new();
+    let project_id = setup.create_project(15, &["cid0"]);
+    setup.contribute(&setup.user1, 5, project_id);
+    advance_ledger(&setup.env, 11);
+
+    // nothing has persisted the expiry yet, not even asking for the state
+    assert_eq!(setup.client().stored_state(&project_id), 0);
+    assert_eq!(setup.client().state(&project_id), 3);
+    assert_eq!(setup.client().stored_state(&project_id), 0);
+
+    setup.client().withdraw(&setup.user1, &project_id);
+    assert_eq!(setup.client().stored_state(&project_id), 3);
+}
+
+fn daily_limit_setup() -> (ProjectSetup<'static>, u32) {
+    let (setup, project_id) = ProjectSetup::with_config(
+        |e| ProjectConfig {
+            daily_reward_limit: 2,
+            ..default_config(e)
+        },
+        &["cid0", "cid1", "cid2"],
+        15,
+    );
+    setup.contribute(&setup.user1, 15, project_id);
+    setup.submit(&setup.user2, "cid0", "cat", project_id);
+    setup.submit(&setup.user2, "cid1", "cat", project_id);
+    (setup, project_id)
+}
+
+#[test]
+#[should_panic(expected = "daily reward limit")]
+fn test_daily_reward_limit_reached() {
+    let (setup, project_id) = daily_limit_setup();
+    setup.submit(&setup.user2, "cid2", "cat", project_id);
+}
+
+#[test]
+fn test_daily_reward_limit_resets_next_day() {
+    let (setup, project_id) = daily_limit_setup();
+    advance_ledger(&setup.env, 86_400);
+
+    setup.submit(&setup.user2, "cid2", "cat", project_id);
+    assert_eq!(setup.token.balance(&setup.user2), 103);
+}
+
+#[test]
+fn test_project_bytes() {
+    let setup = ProjectSetup::new();
+    let e = &setup.env;
+    let project_id = setup.create_project(15, &["cid0"]);
+    setup.contribute(&setup.user1, 15, project_id);
+
+    let bytes = setup.client().project_bytes(&project_id);
+    let decoded = <(u32, Address, Address, u64, i128, i128, u32)>::from_xdr(e, &bytes).unwrap();
+    assert_eq!(
+        decoded,
+        (
+            project_id,
+            setup.recipient.clone(),
+            setup.token.address.clone(),
+            setup.client().deadline(&project_id),
+            15,
+            15,
+            1,
+        )
+    );
+}
+
+fn early_refund_setup(refund_fee_bps: u32) -> (ProjectSetup<'static>, u32) {
+    let (setup, project_id) = ProjectSetup::with_config(
+        |e| ProjectConfig {
+            early_refunds: true,
+            refund_fee_bps,
+            ..default_config(e)
+        },
+        &["cid0"],
+        15,
+    );
+    setup.contribute(&setup.user1, 10, project_id);
+    (setup, project_id)
+}
+
+#[test]
+fn test_early_refund_fee() {
+    let (setup, project_id) = early_refund_setup(1_000);
+    setup.client().set_admin(&Address::generate(&setup.env));
+    let collector = Address::generate(&setup.env);
+    setup.client().set_fee_collector(&collector, &0);
+
+    setup.client().withdraw(&setup.user1, &project_id);
+    assert_eq!(setup.token.balance(&setup.user1), 99);
+    assert_eq!(setup.client().remaining_to_target(&project_id), 15);
+    assert_eq!(setup.client().fees_accrued(&setup.token.address), 1);
+
+    setup.client().collect_fees();
+    assert_eq!(setup.token.balance(&collector), 1);
+}
+
+#[test]
+fn test_early_refund_without_fee() {
+    let (setup, project_id) = early_refund_setup(0);
+
+    setup.client().withdraw(&setup.user1, &project_id);
+    assert_eq!(setup.token.balance(&setup.user1), 100);
+    assert_eq!(setup.client().fees_accrued(&setup.token.address), 0);
+}
+
+#[test]
+fn test_contributor_count() {
+    let setup = ProjectSetup::new();
+    let project_id = setup.create_project(15, &["cid0"]);
+    let user3 = Address::generate(&setup.env);
+    setup.token_admin.mint(&user3, &100);
+
+    setup.contribute(&setup.user1, 2, project_id);
+    setup.contribute(&setup.user2, 2, project_id);
+    setup.contribute(&setup.user1, 2, project_id);
+    setup.contribute(&user3, 2, project_id);
+
+    assert_eq!(setup.client().contribution_count(&project_id), 4);
+    assert_eq!(setup.client().contributor_count(&project_id), 3);
+}
+
+#[test]
+#[should_panic(expected = "insufficient contract balance")]
+fn test_withdraw_with_accounting_mismatch() {
+    let setup = ProjectSetup::new();
+    let project_id = setup.create_project(15, &["cid0"]);
+    setup.contribute(&setup.user1, 10, project_id);
+    setup.contribute(&setup.user2, 4, project_id);
+    advance_ledger(&setup.env, 11);
+    // the books no longer cover user1's deposit
+    setup.set_current_amount(project_id, 5);
+
+    setup.client().withdraw(&setup.user1, &project_id);
+}
+
+#[test]
+fn test_annotations_with_label() {
+    let setup = ProjectSetup::new();
+    let e = &setup.env;
+    let project_id = setup.create_project(15, &["cid0"]);
+    setup.contribute(&setup.user1, 15, project_id);
+    setup.submit(&setup.user1, "cid0", "cat", project_id);
+    setup.submit(&setup.user2, "cid0", "dog", project_id);
+    setup.submit(&setup.recipient, "cid0", "cat", project_id);
+
+    let cats = setup.client().annotations_with_label(
+        &project_id,
+        &Symbol::new(e, "cid0"),
+        &Symbol::new(e, "cat"),
+    );
+    assert_eq!(cats.len(), 2);
+    assert_eq!(cats.get(0).unwrap().annotator, setup.user1);
+    assert_eq!(cats.get(1).unwrap().annotator, setup.recipient);
+    assert!(setup
+        .client()
+        .annotations_with_label(
+            &project_id,
+            &Symbol::new(e, "cid0"),
+            &Symbol::new(e, "bird"),
+        )
+        .is_empty());
+}
+
+#[test]
+#[should_panic(expected = "invalid recipient")]
+fn test_initialize_rejects_contract_recipient() {
+    let setup = ProjectSetup::new();
+    setup.create_project_for(
+        &setup.annotate_id,
+        15,
+        &["cid0"],
+        &default_config(&setup.env),
+    );
+}
+
+#[test]
+fn test_avg_contribution() {
+    let setup = ProjectSetup::new();
+    let project_id = setup.create_project(1_000, &["cid0"]);
+    assert_eq!(setup.client().avg_contribution(&project_id), 0);
+
+    let user3 = Address::generate(&setup.env);
+    setup.token_admin.mint(&setup.user2, &100);
+    setup.token_admin.mint(&user3, &300);
+    setup.contribute(&setup.user1, 100, project_id);
+    setup.contribute(&setup.user2, 200, project_id);
+    setup.contribute(&user3, 300, project_id);
+
+    assert_eq!(setup.client().avg_contribution(&project_id), 200);
+}
+
+#[test]
+fn test_annotation_history() {
+    let (setup, project_id) = edit_setup();
+    let e = &setup.env;
+    let cid = Symbol::new(e, "cid0");
+    for label in ["dog", "bird"] {
+        setup.client().edit_annotation(
+            &project_id,
+            &cid,
+            &setup.user2,
+            &1,
+            &1,
+            &5,
+            &5,
+            &Symbol::new(e, label),
+        );
+    }
+
+    let history = setup.client().annotation_history(&project_id, &cid);
+    assert_eq!(history.len(), 2);
+    assert_eq!(history.get(0).unwrap().label, Symbol::new(e, "cat"));
+    assert_eq!(history.get(1).unwrap().label, Symbol::new(e, "dog"));
+    let current = setup.client().get_annotations(&project_id, &cid);
+    assert_eq!(current.len(), 1);
+    assert_eq!(current.get(0).unwrap().label, Symbol::new(e, "bird"));
+}
+
+#[test]
+fn test_countdown() {
+    let setup = ProjectSetup::new();
+    let project_id = setup.create_project(15, &["cid0"]);
+    // 1 day, 2 hours, 3 minutes and 4 seconds out
+    setup
+        .client()
+        .extend_deadline(&project_id, &(setup.env.ledger().timestamp() + 93_784));
+
+    assert_eq!(setup.client().countdown(&project_id), (1, 2, 3, 4));
+    advance_ledger(&setup.env, 93_785);
+    assert_eq!(setup.client().countdown(&project_id), (0, 0, 0, 0));
+}
+
+#[test]
+fn test_get_projects_by_ids() {
+    let setup = ProjectSetup::new();
+    let first = setup.create_project(15, &["cid0"]);
+    setup.create_project(15, &["cid0"]);
+    let third = setup.create_project(15, &["cid0"]);
+
+    let projects = setup
+        .client()
+        .get_projects_by_ids(&vec![&setup.env, third, 42, first]);
+    assert_eq!(projects.len(), 2);
+    assert_eq!(projects.get(0).unwrap().id, third);
+    assert_eq!(projects.get(1).unwrap().id, first);
+}
+
+#[test]
+fn test_max_total_payout() {
+    let setup = ProjectSetup::new();
+    let config = ProjectConfig {
+        reward_per_annotation: 2,
+        max_total_payout: 3,
+        ..default_config(&setup.env)
+    };
+    let project_id = setup.create_project_with_config(15, &["cid0", "cid1", "cid2"], &config);
+    setup.contribute(&setup.user1, 15, project_id);
+
+    setup.submit(&setup.user2, "cid0", "cat", project_id);
+    setup.submit(&setup.user2, "cid1", "cat", project_id);
+    // the second reward was trimmed to what the cap had left
+    assert_eq!(setup.token.balance(&setup.user2), 103);
+    assert_eq!(setup.client().state(&project_id), 2);
+
+    setup.submit(&setup.user2, "cid2", "cat", project_id);
+    assert_eq!(setup.token.balance(&setup.user2), 103);
+    assert_eq!(setup.client().total_earnings_paid(&project_id), 3);
+    assert_eq!(setup.client().contributor_count(&project_id), 1);
+}
+
+#[test]
+fn test_get_annotators() {
+    let setup = ProjectSetup::new();
+    let project_id = setup.create_project(15, &["cid0", "cid1", "cid2"]);
+    setup.contribute(&setup.user1, 15, project_id);
+    setup.submit(&setup.user2, "cid0", "cat", project_id);
+    setup.submit(&setup.user1, "cid1", "cat", project_id);
+    setup.submit(&setup.user2, "cid2", "cat", project_id);
+
+    let annotators = setup.client().get_annotators(&project_id);
+    assert_eq!(annotators.len(), 2);
+    assert!(annotators.contains(&setup.user1));
+    assert!(annotators.contains(&setup.user2));
+}
+
+fn rounding_setup(rounding: &str) -> (ProjectSetup<'static>, u32) {
+    let (setup, project_id) = ProjectSetup::with_config(
+        |e| ProjectConfig {
+            annotation_deadline: e.ledger().timestamp() + 100,
+            rounding: Symbol::new(e, rounding),
+            ..default_config(e)
+        },
+        &["cid0"],
+        15,
+    );
+    setup.contribute(&setup.user1, 10, project_id);
+    setup.contribute(&setup.user2, 5, project_id);
+    setup.submit(&setup.recipient, "cid0", "cat", project_id);
+    advance_ledger(&setup.env, 101);
+    // 14 left to share 10:5, which doesn't divide evenly
+    setup.submit(&setup.user1, "cid0", "cat", project_id);
+    setup.submit(&setup.user2, "cid0", "cat", project_id);
+    (setup, project_id)
+}
+
+#[test]
+fn test_refund_rounding_floor() {
+    let (setup, project_id) = rounding_setup("floor");
+
+    assert_eq!(setup.token.balance(&setup.user1), 99);
+    assert_eq!(setup.token.balance(&setup.user2), 99);
+    assert_eq!(setup.client().sweep_remainder(&project_id), 1);
+    // one for the annotation, one of dust
+    assert_eq!(setup.token.balance(&setup.recipient), 2);
+}
+
+#[test]
+fn test_refund_rounding_ceil() {
+    let (setup, project_id) = rounding_setup("ceil");
+
+    // user1 rounds 9.33 up to 10, user2 is capped at the 4 left rather than 5
+    assert_eq!(setup.token.balance(&setup.user1), 100);
+    assert_eq!(setup.token.balance(&setup.user2), 99);
+    assert_eq!(setup.client().sweep_remainder(&project_id), 0);
+    assert_eq!(setup.token.balance(&setup.recipient), 1);
+    assert_eq!(setup.token.balance(&setup.annotate_id), 0);
+}
+
+#[test]
+fn test_annotator_headroom() {
+    let setup = ProjectSetup::new();
+    let config = ProjectConfig {
+        max_reward_per_annotator: 3,
+        ..default_config(&setup.env)
+    };
+    let project_id = setup.create_project_with_config(15, &["cid0", "cid1", "cid2"], &config);
+    let uncapped = setup.create_project(15, &["cid0"]);
+    setup.contribute(&setup.user1, 15, project_id);
+    setup.submit(&setup.user2, "cid0", "cat", project_id);
+    setup.submit(&setup.user2, "cid1", "cat", project_id);
+
+    assert_eq!(
+        setup.client().annotator_headroom(&project_id, &setup.user2),
+        1
+    );
+    assert_eq!(
+        setup.client().annotator_headroom(&project_id, &setup.user1),
+        3
+    );
+    assert_eq!(
+        setup.client().annotator_headroom(&uncapped, &setup.user2),
+        i128::MAX
+    );
+
+    setup.submit(&setup.user2, "cid2", "cat", project_id);
+    assert_eq!(
+        setup.client().annotator_headroom(&project_id, &setup.user2),
+        0
+    );
+}
+
+#[test]
+#[should_panic(expected = "annotator cap reached")]
+fn test_annotator_cap_reached() {
+    let setup = ProjectSetup::new();
+    let config = ProjectConfig {
+        max_reward_per_annotator: 1,
+        ..default_config(&setup.env)
+    };
+    let project_id = setup.create_project_with_config(15, &["cid0", "cid1"], &config);
+    setup.contribute(&setup.user1, 15, project_id);
+    setup.submit(&setup.user2, "cid0", "cat", project_id);
+
+    setup.submit(&setup.user2, "cid1", "cat", project_id);
+}
+
+#[test]
+fn test_recover_stray_token() {
+    let setup = ProjectSetup::new();
+    let admin = Address::generate(&setup.env);
+    setup.client().set_admin(&admin);
+    setup.create_project(15, &["cid0"]);
+    let (stray, stray_admin) = create_token_contract(&setup.env, &admin);
+    stray_admin.mint(&setup.annotate_id, &7);
+
+    setup
+        .client()
+        .recover_token(&stray.address, &setup.user1, &7);
+    assert_eq!(setup.env.auths()[0].0, admin);
+    assert_eq!(stray.balance(&setup.user1), 7);
+    assert_eq!(stray.balance(&setup.annotate_id), 0);
+}
+
+#[test]
+#[should_panic(expected = "project token")]
+fn test_recover_project_token_rejected() {
+    let setup = ProjectSetup::new();
+    setup.client().set_admin(&Address::generate(&setup.env));
+    let project_id = setup.create_project(15, &["cid0"]);
+    setup.contribute(&setup.user1, 10, project_id);
+
+    setup
+        .client()
+        .recover_token(&setup.token.address, &setup.user1, &10);
+}
+
+#[test]
+fn test_quote_reward_matches_payout() {
+    let setup = ProjectSetup::new();
+    let e = &setup.env;
+    let mut config = multiplier_config(e, 300);
+    config.reward_per_annotation = 4;
+    config.reward_halving_interval = 10;
+    config.max_total_payout = 9;
+    let project_id = setup.create_project_with_config(15, &["cid0", "cid1"], &config);
+    setup.contribute(&setup.user1, 15, project_id);
+    advance_ledger(e, 10);
+
+    let cid = Symbol::new(e, "cid0");
+    let hard = Symbol::new(e, "hard");
+    // halved to 2, tripled for the label
+    let quote = setup
+        .client()
+        .quote_reward(&project_id, &cid, &hard, &setup.user2);
+    assert_eq!(quote, 6);
+    setup.submit(&setup.user2, "cid0", "hard", project_id);
+    assert_eq!(setup.token.balance(&setup.user2), 100 + quote);
+
+    // only 3 left under the payout cap
+    let quote =
+        setup
+            .client()
+            .quote_reward(&project_id, &Symbol::new(e, "cid1"), &hard, &setup.user2);
+    assert_eq!(quote, 3);
+    setup.submit(&setup.user2, "cid1", "hard", project_id);
+    assert_eq!(setup.token.balance(&setup.user2), 106 + quote);
+}
+
+#[test]
+fn test_quote_reward_zero_when_submit_would_not_pay() {
+    let (setup, project_id) = cooldown_setup();
+    let e = &setup.env;
+    let cid = Symbol::new(e, "cid1");
+    let cat = Symbol::new(e, "cat");
+    assert_eq!(
+        setup
+            .client()
+            .quote_reward(&project_id, &cid, &cat, &setup.user2),
+        0
+    );
+    assert_eq!(
+        setup
+            .client()
+            .quote_reward(&project_id, &cid, &cat, &setup.user1),
+        1
+    );
+
+    setup.client().set_admin(&Address::generate(e));
+    setup.client().ban_annotator(&setup.user1);
+    assert_eq!(
+        setup
+            .client()
+            .quote_reward(&project_id, &cid, &cat, &setup.user1),
+        0
+    );
+}
+
+fn reentrant_setup() -> (ProjectSetup<'static>, ReentrantTokenClient<'static>, u32) {
+    let setup = ProjectSetup::new();
+    let e = &setup.env;
+    let token = ReentrantTokenClient::new(e, &register_reentrant_token(e));
+    let project_id = setup.create_project_with_token(
+        &setup.recipient,
+        &token.address,
+        15,
+        &["cid0"],
+        &default_config(e),
+    );
+    (setup, token, project_id)
+}
+
+#[test]
+fn test_reentrant_token_disarmed() {
+    let (setup, token, project_id) = reentrant_setup();
+
+    setup
+        .client()
+        .contribute(&setup.user1, &token.address, &5, &project_id);
+    assert_eq!(setup.client().balance(&setup.user1, &project_id), 5);
+}
+
+#[test]
+fn test_reentrant_token_blocked() {
+    let (setup, token, project_id) = reentrant_setup();
+    token.arm(&setup.annotate_id, &project_id);
+
+    // the token tries to withdraw mid-contribution, the host refuses re-entry
+    // and the whole contribution is rolled back
+    assert!(setup
+        .client()
+        .try_contribute(&setup.user1, &token.address, &5, &project_id)
+        .is_err());
+    assert_eq!(setup.client().balance(&setup.user1, &project_id), 0);
+    assert_eq!(setup.client().contribution_count(&project_id), 0);
+}
+
+#[test]
+fn test_is_annotating() {
+    let setup = ProjectSetup::new();
+    let project_id = setup.create_project(1, &["cid0"]);
+    assert!(!setup.client().is_annotating(&project_id));
+
+    setup.contribute(&setup.user1, 1, project_id);
+    assert!(setup.client().is_annotating(&project_id));
+
+    setup.submit(&setup.user2, "cid0", "cat", project_id);
+    assert_eq!(setup.client().state(&project_id), 2);
+    assert!(!setup.client().is_annotating(&project_id));
+}
+
+fn approval_setup() -> (ProjectSetup<'static>, u32) {
+    let (setup, project_id) = ProjectSetup::with_config(
+        |e| ProjectConfig {
+            approval_required: true,
+            ..default_config(e)
+        },
+        &["cid0", "cid1"],
+        15,
+    );
+    setup.contribute(&setup.user1, 15, project_id);
+    setup.submit(&setup.user2, "cid0", "cat", project_id);
+    (setup, project_id)
+}
+
+#[test]
+fn test_approve_annotation_pays_reward() {
+    let (setup, project_id) = approval_setup();
+    let cid = Symbol::new(&setup.env, "cid0");
+    // held until reviewed
+    assert!(setup.client().get_annotation(&project_id, &cid, &0).pending);
+    assert_eq!(setup.token.balance(&setup.user2), 100);
+    assert_eq!(setup.client().global_stats().2, 0);
+
+    setup.client().approve_annotation(&project_id, &cid, &0);
+    assert!(!setup.client().get_annotation(&project_id, &cid, &0).pending);
+    assert_eq!(setup.token.balance(&setup.user2), 101);
+    assert_eq!(setup.client().global_stats().2, 1);
+}
+
+#[test]
+fn test_reject_annotation_discards_it() {
+    let (setup, project_id) = approval_setup();
+    let cid = Symbol::new(&setup.env, "cid0");
+
+    setup.client().reject_annotation(&project_id, &cid, &0);
+    assert_eq!(setup.client().get_annotations(&project_id, &cid).len(), 0);
+    assert_eq!(setup.token.balance(&setup.user2), 100);
+    assert!(setup
+        .client()
+        .try_approve_annotation(&project_id, &cid, &0)
+        .is_err());
+}
+
+#[test]
+fn test_reject_annotation_moves_later_disputes() {
+    let setup = ProjectSetup::new();
+    let config = ProjectConfig {
+        approval_required: true,
+        ..staked_config(&setup.env)
+    };
+    let project_id = setup.create_project_with_config(15, &["cid0"], &config);
+    setup.contribute(&setup.user1, 15, project_id);
+    setup.client().stake(&setup.user1, &project_id);
+    setup.client().stake(&setup.user2, &project_id);
+    setup.submit(&setup.user1, "cid0", "cat", project_id);
+    setup.submit(&setup.user2, "cid0", "dog", project_id);
+
+    let cid = Symbol::new(&setup.env, "cid0");
+    setup.client().approve_annotation(&project_id, &cid, &1);
+    setup
+        .client()
+        .open_dispute(&setup.recipient, &project_id, &cid, &0);
+    setup
+        .client()
+        .open_dispute(&setup.recipient, &project_id, &cid, &1);
+
+    // user2's annotation moves to index 0 and its dispute moves with it
+    setup.client().reject_annotation(&project_id, &cid, &0);
+    assert!(setup
+        .client()
+        .try_resolve_dispute(&project_id, &cid, &1, &true)
+        .is_err());
+    setup.client().resolve_dispute(&project_id, &cid, &0, &true);
+    assert_eq!(setup.client().get_stake(&setup.user2, &project_id), 0);
+    assert_eq!(setup.client().get_stake(&setup.user1, &project_id), 20);
+    assert!(setup
+        .client()
+        .get_project(&project_id, &false)
+        .disputes
+        .is_empty());
+}
+
+#[test]
+fn test_contract_balance() {
+    let setup = ProjectSetup::new();
+    let funded = setup.create_project(15, &["cid0", "cid1"]);
+    let expired = setup.create_project(15, &["cid0"]);
+    assert_eq!(setup.client().contract_balance(), 0);
+
+    setup.contribute(&setup.user1, 15, funded);
+    setup.contribute(&setup.user2, 10, expired);
+    advance_ledger(&setup.env, 11);
+    setup.submit(&setup.user1, "cid0", "cat", funded);
+    setup.submit(&setup.user1, "cid1", "cat", funded);
+    setup.client().withdraw(&setup.user2, &expired);
+
+    // deposits minus payouts minus refunds
+    assert_eq!(setup.client().contract_balance(), 15 + 10 - 2 - 10);
+}
+
+fn annotation_cap_setup() -> (ProjectSetup<'static>, u32) {
+    let (setup, project_id) = ProjectSetup::with_config(
+        |e| ProjectConfig {
+            required_annotations: 3,
+            max_annotations_per_cid: 2,
+            ..default_config(e)
+        },
+        &["cid0"],
+        15,
+    );
+    setup.contribute(&setup.user1, 15, project_id);
+    setup.submit(&setup.user2, "cid0", "cat", project_id);
+    setup.submit(&setup.user2, "cid0", "cat", project_id);
+    (setup, project_id)
+}
+
+#[test]
+fn test_annotation_cap_fills_up() {
+    let (setup, project_id) = annotation_cap_setup();
+    let cid = Symbol::new(&setup.env, "cid0");
+    assert_eq!(setup.client().get_annotations(&project_id, &cid).len(), 2);
+    assert_eq!(setup.token.balance(&setup.user2), 102);
+}
+
+#[test]
+#[should_panic(expected = "annotation cap reached")]
+fn test_annotation_cap_rejects_next() {
+    let (setup, project_id) = annotation_cap_setup();
+    // still short of the 3 required, but the data point is full
+    setup.submit(&setup.user1, "cid0", "cat", project_id);
+}
+
+#[test]
+fn test_finalized_at() {
+    let setup = ProjectSetup::new();
+    let config = ProjectConfig {
+        required_annotations: 2,
+        ..default_config(&setup.env)
+    };
+    let project_id = setup.create_project_with_config(15, &["cid0"], &config);
+    let cid = Symbol::new(&setup.env, "cid0");
+    setup.contribute(&setup.user1, 15, project_id);
+    advance_ledger(&setup.env, 5);
+    setup.submit(&setup.user2, "cid0", "cat", project_id);
+    assert_eq!(setup.client().finalized_at(&project_id, &cid), 0);
+
+    advance_ledger(&setup.env, 7);
+    setup.submit(&setup.user1, "cid0", "cat", project_id);
+    assert_eq!(setup.client().finalized_at(&project_id, &cid), 12);
+}
+
+#[test]
+fn test_reputation_weighted_consensus() {
+    let setup = ProjectSetup::new();
+    setup.client().set_admin(&Address::generate(&setup.env));
+    let expert = Address::generate(&setup.env);
+    setup.client().set_reputation(&expert, &5);
+    assert_eq!(setup.client().reputation(&expert), 5);
+    assert_eq!(setup.client().reputation(&setup.user1), 1);
+
+    let config = ProjectConfig {
+        required_annotations: 3,
+        ..default_config(&setup.env)
+    };
+    let project_id = setup.create_project_with_config(15, &["cid0"], &config);
+    setup.contribute(&setup.user1, 15, project_id);
+    setup.submit(&setup.user1, "cid0", "cat", project_id);
+    setup.submit(&setup.user2, "cid0", "cat", project_id);
+    setup.submit(&expert, "cid0", "dog", project_id);
+
+    // two votes of weight 1 lose to one of weight 5
+    assert_eq!(
+        setup
+            .client()
+            .consensus_label(&project_id, &Symbol::new(&setup.env, "cid0")),
+        Symbol::new(&setup.env, "dog")
+    );
+}
+
+#[test]
+fn test_user_summary() {
+    let setup = ProjectSetup::new();
+    setup.token_admin.mint(&setup.user1, &400);
+    let project_id = setup.create_project(510, &["cid0", "cid1", "cid2"]);
+    setup.contribute(&setup.user1, 500, project_id);
+    setup.contribute(&setup.user2, 10, project_id);
+    setup.submit(&setup.user1, "cid0", "cat", project_id);
+    setup.submit(&setup.user1, "cid1", "dog", project_id);
+
+    assert_eq!(
+        setup.client().user_summary(&project_id, &setup.user1),
+        (500, 2, 2)
+    );
+    assert_eq!(
+        setup.client().user_summary(&project_id, &setup.user2),
+        (10, 0, 0)
+    );
+}
+
+fn reinvest_setup(track_per_contributor: bool) -> (ProjectSetup<'static>, u32) {
+    let (setup, project_id) = ProjectSetup::with_config(
+        |e| ProjectConfig {
+            batch_rewards: true,
+            track_per_contributor,
+            ..default_config(e)
+        },
+        &["cid0", "cid1", "cid2"],
+        15,
+    );
+    setup.contribute(&setup.user1, 15, project_id);
+    setup.submit(&setup.user2, "cid0", "cat", project_id);
+    setup.submit(&setup.user2, "cid1", "cat", project_id);
+    (setup, project_id)
+}
+
+#[test]
+fn test_reinvest_earnings() {
+    let (setup, project_id) = reinvest_setup(true);
+    assert_eq!(setup.client().balance(&setup.user2, &project_id), 0);
+
+    setup.client().reinvest_earnings(&setup.user2, &project_id);
+    assert_eq!(setup.client().balance(&setup.user2, &project_id), 2);
+    assert_eq!(setup.client().total_earnings_paid(&project_id), 0);
+    let project = setup.client().get_project(&project_id, &false);
+    assert_eq!(
+        project
+            .pending_rewards
+            .get(setup.user2.clone())
+            .unwrap_or(0),
+        0
+    );
+
+    // nothing left to pay out at close
+    setup.submit(&setup.user1, "cid2", "cat", project_id);
+    setup.client().finalize(&project_id);
+    assert_eq!(setup.token.balance(&setup.user2), 100);
+}
+
+#[test]
+#[should_panic(expected = "per-contributor tracking disabled")]
+fn test_reinvest_earnings_needs_tracking() {
+    let (setup, project_id) = reinvest_setup(false);
+    setup.client().reinvest_earnings(&setup.user2, &project_id);
+}
+
+#[test]
+fn test_is_valid_cid() {
+    let setup = ProjectSetup::new();
+    let e = &setup.env;
+    assert!(setup
+        .client()
+        .is_valid_cid(&Symbol::new(e, "bafkreihdwdcefgh4dqkjv67uzcmw7o")));
+    assert!(!setup.client().is_valid_cid(&Symbol::new(e, "ab")));
+    assert!(!setup.client().is_valid_cid(&Symbol::new(e, "c_1")));
+    assert!(setup.client().is_valid_cid(&Symbol::new(e, "cid_1")));
+
+    let project_id = setup.create_project(15, &["bafkreihdwdcefgh4dqkjv67uzcmw7o"]);
+    assert_eq!(setup.client().data_point_count(&project_id), 1);
+}
+
+#[test]
+#[should_panic(expected = "invalid cid")]
+fn test_create_project_rejects_bad_cid() {
+    let setup = ProjectSetup::new();
+    setup.create_project(15, &["cid0", "x"]);
+}
+
+#[test]
+fn test_projects_by_progress() {
+    let setup = ProjectSetup::new();
+    let half = setup.create_project(10, &["cid0"]);
+    let tenth = setup.create_project(10, &["cid0"]);
+    let most = setup.create_project(10, &["cid0"]);
+    setup.contribute(&setup.user1, 5, half);
+    setup.contribute(&setup.user1, 1, tenth);
+    setup.contribute(&setup.user1, 9, most);
+
+    let all = setup.client().projects_by_progress(&10);
+    assert_eq!(all.len(), 3);
+    assert_eq!(all.get(0).unwrap().id, most);
+    assert_eq!(all.get(0).unwrap().progress_bps, 9_000);
+    assert_eq!(all.get(1).unwrap().id, half);
+    assert_eq!(all.get(2).unwrap().id, tenth);
+
+    let top = setup.client().projects_by_progress(&2);
+    assert_eq!(top.len(), 2);
+    assert_eq!(top.get(1).unwrap().id, half);
+}
+
+#[test]
+#[should_panic(expected = "project not found")]
+fn test_contribute_unknown_project() {
+    let setup = ProjectSetup::new();
+    setup.create_project(15, &["cid0"]);
+    setup.contribute(&setup.user1, 5, 999);
+}
+
+#[test]
+fn test_projected_total_payout() {
+    let setup = ProjectSetup::new();
+    let config = ProjectConfig {
+        reward_per_annotation: 5,
+        required_annotations: 2,
+        ..default_config(&setup.env)
+    };
+    let project_id = setup.create_project_with_config(30, &["cid0", "cid1", "cid2"], &config);
+    assert_eq!(setup.client().projected_total_payout(&project_id), 30);
+}
+
+#[test]
+fn test_instructions() {
+    let setup = ProjectSetup::new();
+    let text = "Box every pedestrian, label partially hidden ones too.";
+    let config = ProjectConfig {
+        instructions: String::from_str(&setup.env, text),
+        ..default_config(&setup.env)
+    };
+    let project_id = setup.create_project_with_config(15, &["cid0"], &config);
+    let plain = setup.create_project(15, &["cid0"]);
+
+    assert_eq!(
+        setup.client().instructions(&project_id),
+        String::from_str(&setup.env, text)
+    );
+    assert_eq!(
+        setup.client().instructions(&plain),
+        String::from_str(&setup.env, "")
+    );
 }
@@ -1,8 +1,8 @@
 #![cfg(test)]
 
-use crate::CrowdfundClient;
+use crate::DataAnnotateClient;
 
-use soroban_sdk::{Address, Env};
+use soroban_sdk::{contract, contractimpl, contracttype, Address, Env};
 
 pub fn register_test_contract(e: &Env) -> Address {
     e.register_contract(None, crate::DataAnnotate {})
@@ -15,8 +15,8 @@ pub struct DataAnnotate {
 
 impl DataAnnotate {
     #[must_use]
-    pub fn client(&self) -> CrowdfundClient {
-        CrowdfundClient::new(&self.env, &self.contract_id)
+    pub fn client(&self) -> DataAnnotateClient<'_> {
+        DataAnnotateClient::new(&self.env, &self.contract_id)
     }
 
     #[must_use]
@@ -27,3 +27,53 @@ impl DataAnnotate {
         }
     }
 }
+
+#[contracttype]
+enum ReentrantTokenKey {
+    Target,
+    ProjectId,
+}
+
+/// Token that, once armed, calls back into the annotation contract from inside
+/// `transfer` by withdrawing for the sender. Used to check re-entry is refused.
+#[contract]
+pub struct ReentrantToken;
+
+#[contractimpl]
+impl ReentrantToken {
+    pub fn arm(e: Env, target: Address, project_id: u32) {
+        e.storage()
+            .instance()
+            .set(&ReentrantTokenKey::Target, &target);
+        e.storage()
+            .instance()
+            .set(&ReentrantTokenKey::ProjectId, &project_id);
+    }
+
+    pub fn transfer(e: Env, from: Address, _to: Address, _amount: i128) {
+        let target = e
+            .storage()
+            .instance()
+            .get::<_, Address>(&ReentrantTokenKey::Target);
+        if let Some(target) = target {
+            let project_id: u32 = e
+                .storage()
+                .instance()
+                .get(&ReentrantTokenKey::ProjectId)
+                .unwrap();
+            DataAnnotateClient::new(&e, &target).withdraw(&from, &project_id);
+        }
+    }
+
+    pub fn balance(_e: Env, _id: Address) -> i128 {
+        0
+    }
+
+    pub fn decimals(_e: Env) -> u32 {
+        7
+    }
+}
+
+pub fn register_reentrant_token(e: &Env) -> Address {
+    e.register_contract(None, ReentrantToken {})
+}